@@ -2,26 +2,221 @@
 
 mod notes;
 
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use tauri::Manager;
 
-use notes::ParcelData;
+use notes::{
+    ArchivedFolderFix, DataDiff, DeleteReport, EditAgeSummary, ExportFormat, ExportOptions,
+    ExportOutput, FieldInfo, Folder, FolderStat, ImportDecision, ImportOptions, ImportPlan,
+    ImportReport, LoadDiagnostics, Note, NotePatch, OutlineEntry, ParcelData, ParcelError,
+    SortOrder, StreakInfo, VolumeInfo,
+};
+
+// Set by `open_readonly` to the data directory being browsed. While set, every
+// command reads from there and anything that would write fails.
+#[derive(Default)]
+struct ReadOnlySession(Mutex<Option<PathBuf>>);
+
+// Set once a load had to fall back to a backup. The recovery rewrites
+// notes.json, so later loads can't tell it happened.
+#[derive(Default)]
+struct RecoveredFromBackup(Mutex<bool>);
+
+fn read_only_dir(app: &tauri::AppHandle) -> Option<PathBuf> {
+    app.state::<ReadOnlySession>().0.lock().ok().and_then(|dir| dir.clone())
+}
+
+// Where to read data from: the read-only directory if one is open
+fn app_data_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    if let Some(dir) = read_only_dir(app) {
+        return Ok(dir);
+    }
+    own_data_dir(app)
+}
+
+fn own_data_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .app_data_dir()
+        .map_err(|e| format!("app_data_dir error: {e}"))
+}
+
+// Where to write data to; everything that touches files in the data directory
+// must come through here
+fn writable_data_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    if read_only_dir(app).is_some() {
+        return Err(format!("read-only error: {}", ParcelError::ReadOnly));
+    }
+    own_data_dir(app)
+}
+
+// Every command sees edits still in the WAL, applied in memory. Only
+// `load_notes` and `flush_now` checkpoint them into the data file.
+fn load_data(app: &tauri::AppHandle) -> Result<ParcelData, String> {
+    if let Some(dir) = read_only_dir(app) {
+        return notes::load_read_only(dir)
+            .map(|(data, _)| data)
+            .map_err(|e| format!("load error: {e}"));
+    }
+    let (data, diagnostics) =
+        notes::load_with_wal(writable_data_dir(app)?).map_err(|e| format!("load error: {e}"))?;
+    if diagnostics.recovered_from_backup {
+        if let Ok(mut recovered) = app.state::<RecoveredFromBackup>().0.lock() {
+            *recovered = true;
+        }
+    }
+    Ok(data)
+}
+
+// Data from `load_data` already holds the WAL edits, so a full save of it
+// makes them redundant
+fn save_data(app: &tauri::AppHandle, data: &mut ParcelData) -> Result<(), String> {
+    let dir = writable_data_dir(app)?;
+    notes::save(dir.clone(), data).map_err(|e| format!("save error: {e}"))?;
+    notes::clear_wal(dir).map_err(|e| format!("wal error: {e}"))
+}
+
+// Single-note edits go to the WAL instead of rewriting the data file; the next
+// full save or `flush_now` checkpoints them
+fn log_edit(app: &tauri::AppHandle, note: &Note) -> Result<(), String> {
+    notes::append_wal(writable_data_dir(app)?, note).map_err(|e| format!("wal error: {e}"))
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
 
+// Called at startup, which is when edits left in the WAL by a crash are
+// checkpointed
 #[tauri::command]
 fn load_notes(app: tauri::AppHandle) -> Result<ParcelData, String> {
-    let dir = app
-        .path()
-        .app_data_dir()
-        .map_err(|e| format!("app_data_dir error: {e}"))?;
-    notes::load(dir).map_err(|e| format!("load error: {e}"))
+    // Load before checkpointing so `load_data` sees a recovery from backup
+    // whether or not there are edits to replay
+    let data = load_data(&app)?;
+    if read_only_dir(&app).is_some() {
+        return Ok(data);
+    }
+    let replayed =
+        notes::replay_wal(writable_data_dir(&app)?).map_err(|e| format!("wal error: {e}"))?;
+    if replayed == 0 {
+        return Ok(data);
+    }
+    load_data(&app)
 }
 
 #[tauri::command]
-fn save_notes(app: tauri::AppHandle, data: ParcelData) -> Result<(), String> {
-    let dir = app
-        .path()
-        .app_data_dir()
-        .map_err(|e| format!("app_data_dir error: {e}"))?;
-    notes::save(dir, &data).map_err(|e| format!("save error: {e}"))
+fn get_load_diagnostics(app: tauri::AppHandle) -> Result<LoadDiagnostics, String> {
+    if let Some(dir) = read_only_dir(&app) {
+        return notes::load_read_only(dir)
+            .map(|(_, diagnostics)| diagnostics)
+            .map_err(|e| format!("load error: {e}"));
+    }
+    let (_, mut diagnostics) = notes::load_with_diagnostics(writable_data_dir(&app)?)
+        .map_err(|e| format!("load error: {e}"))?;
+    if let Ok(recovered) = app.state::<RecoveredFromBackup>().0.lock() {
+        diagnostics.recovered_from_backup |= *recovered;
+    }
+    Ok(diagnostics)
+}
+
+// Browse the data under `path` (a directory holding a parcel/ folder) without
+// being able to change it, until `close_readonly`
+#[tauri::command]
+fn open_readonly(app: tauri::AppHandle, path: String) -> Result<ParcelData, String> {
+    let dir = PathBuf::from(path);
+    let (data, _) = notes::load_read_only(dir.clone()).map_err(|e| format!("load error: {e}"))?;
+    let session = app.state::<ReadOnlySession>();
+    *session.0.lock().map_err(|e| format!("state error: {e}"))? = Some(dir);
+    Ok(data)
+}
+
+#[tauri::command]
+fn close_readonly(app: tauri::AppHandle) -> Result<(), String> {
+    let session = app.state::<ReadOnlySession>();
+    *session.0.lock().map_err(|e| format!("state error: {e}"))? = None;
+    Ok(())
+}
+
+#[tauri::command]
+fn is_read_only(app: tauri::AppHandle) -> bool {
+    read_only_dir(&app).is_some()
+}
+
+#[tauri::command]
+fn get_save_seq(app: tauri::AppHandle) -> Result<u64, String> {
+    Ok(load_data(&app)?.save_seq)
+}
+
+#[tauri::command]
+fn save_notes(app: tauri::AppHandle, mut data: ParcelData) -> Result<(), String> {
+    if let Ok(stored) = load_data(&app) {
+        notes::carry_over_settings(&mut data, &stored);
+        notes::carry_over_revisions(&mut data, &stored);
+        notes::keep_protected_notes(&mut data, &stored);
+    }
+    notes::backup_if_due(writable_data_dir(&app)?, now_ms(), data.backup_min_interval_ms)
+        .map_err(|e| format!("backup error: {e}"))?;
+    // A full save from the frontend includes every logged edit
+    save_data(&app, &mut data)
+}
+
+#[tauri::command]
+fn log_note_edit(app: tauri::AppHandle, note: Note) -> Result<(), String> {
+    log_edit(&app, &note)
+}
+
+#[tauri::command]
+fn replay_wal(app: tauri::AppHandle) -> Result<usize, String> {
+    notes::replay_wal(writable_data_dir(&app)?).map_err(|e| format!("wal error: {e}"))
+}
+
+#[tauri::command]
+fn pending_changes(app: tauri::AppHandle) -> Result<Vec<String>, String> {
+    notes::pending_changes(app_data_dir(&app)?).map_err(|e| format!("wal error: {e}"))
+}
+
+// Checkpoint logged edits into the data file right away
+#[tauri::command]
+fn flush_now(app: tauri::AppHandle) -> Result<(), String> {
+    replay_wal(app).map(|_| ())
+}
+
+#[tauri::command]
+fn get_export_size_estimate(app: tauri::AppHandle, format: ExportFormat) -> Result<usize, String> {
+    let data = load_data(&app)?;
+    Ok(notes::estimate_export_size(&data, format))
+}
+
+#[tauri::command]
+fn export_notes(
+    app: tauri::AppHandle,
+    format: ExportFormat,
+    options: Option<ExportOptions>,
+) -> Result<ExportOutput, String> {
+    let data = load_data(&app)?;
+    notes::export(&data, format, &options.unwrap_or_default())
+        .map_err(|e| format!("export error: {e}"))
+}
+
+#[tauri::command]
+fn export_bundle(app: tauri::AppHandle) -> Result<Vec<u8>, String> {
+    let data = load_data(&app)?;
+    notes::export_bundle(&data).map_err(|e| format!("export error: {e}"))
+}
+
+// Replaces the stored data with a verified bundle, backing up first
+#[tauri::command]
+fn import_bundle(app: tauri::AppHandle, bytes: Vec<u8>) -> Result<usize, String> {
+    let mut data = notes::read_bundle(&bytes).map_err(|e| format!("import error: {e}"))?;
+    notes::backup(writable_data_dir(&app)?, now_ms()).map_err(|e| format!("backup error: {e}"))?;
+    save_data(&app, &mut data)?;
+    Ok(data.notes.len())
 }
 
 #[tauri::command]
@@ -30,27 +225,948 @@ fn export_notes_json(data: ParcelData) -> Result<String, String> {
 }
 
 #[tauri::command]
-fn export_notes_markdown(data: ParcelData) -> Result<String, String> {
-    notes::export_markdown(&data).map_err(|e| format!("export error: {e}"))
+fn export_notes_yaml(data: ParcelData) -> Result<String, String> {
+    notes::export_yaml(&data).map_err(|e| format!("export error: {e}"))
+}
+
+#[tauri::command]
+fn import_notes_yaml(yaml: String) -> Result<ParcelData, String> {
+    notes::import_yaml(&yaml).map_err(|e| format!("import error: {e}"))
+}
+
+#[tauri::command]
+fn export_notes_tree_zip(app: tauri::AppHandle) -> Result<Vec<u8>, String> {
+    let data = load_data(&app)?;
+    notes::export_tree_zip(&data).map_err(|e| format!("export error: {e}"))
+}
+
+#[tauri::command]
+fn export_folders_json(app: tauri::AppHandle, root_path: String) -> Result<usize, String> {
+    let data = load_data(&app)?;
+    notes::export_folders_json_tree(&data, &PathBuf::from(root_path), false)
+        .map_err(|e| format!("export error: {e}"))
+}
+
+#[tauri::command]
+fn export_by_date_tree(app: tauri::AppHandle, root_path: String) -> Result<usize, String> {
+    let data = load_data(&app)?;
+    notes::export_folders_json_tree(&data, &PathBuf::from(root_path), true)
+        .map_err(|e| format!("export error: {e}"))
+}
+
+#[tauri::command]
+fn get_schema_fields(version: u32) -> Result<Vec<FieldInfo>, String> {
+    notes::schema_fields(version).map_err(|e| format!("read error: {e}"))
+}
+
+#[tauri::command]
+fn export_json_version(app: tauri::AppHandle, version: u32) -> Result<String, String> {
+    let data = load_data(&app)?;
+    notes::export_json_at_version(&data, version).map_err(|e| format!("export error: {e}"))
+}
+
+#[tauri::command]
+fn export_notes_markdown(data: ParcelData, options: Option<ExportOptions>) -> Result<String, String> {
+    notes::export_markdown(&data, &options.unwrap_or_default())
+        .map_err(|e| format!("export error: {e}"))
+}
+
+#[tauri::command]
+fn export_notes_docx(app: tauri::AppHandle) -> Result<Vec<u8>, String> {
+    let data = load_data(&app)?;
+    notes::export_docx(&data).map_err(|e| format!("export error: {e}"))
+}
+
+#[tauri::command]
+fn export_notes_pdf(app: tauri::AppHandle) -> Result<Vec<u8>, String> {
+    let data = load_data(&app)?;
+    notes::export_pdf(&data).map_err(|e| format!("export error: {e}"))
+}
+
+#[tauri::command]
+fn export_notes_org(app: tauri::AppHandle) -> Result<String, String> {
+    let data = load_data(&app)?;
+    notes::export_org(&data).map_err(|e| format!("export error: {e}"))
+}
+
+#[tauri::command]
+fn set_reminder(app: tauri::AppHandle, note_id: String, at: Option<u64>) -> Result<(), String> {
+    let mut data = load_data(&app)?;
+    notes::set_reminder(&mut data, &note_id, at).map_err(|e| format!("update error: {e}"))?;
+    save_data(&app, &mut data)
+}
+
+#[tauri::command]
+fn export_reminders_ics(app: tauri::AppHandle) -> Result<String, String> {
+    let data = load_data(&app)?;
+    notes::export_ics(&data).map_err(|e| format!("export error: {e}"))
+}
+
+#[tauri::command]
+fn export_tags(app: tauri::AppHandle) -> Result<String, String> {
+    let data = load_data(&app)?;
+    notes::export_tags_index(&data).map_err(|e| format!("export error: {e}"))
+}
+
+#[tauri::command]
+fn get_notes_by_tag(app: tauri::AppHandle) -> Result<HashMap<String, Vec<String>>, String> {
+    let data = load_data(&app)?;
+    Ok(notes::notes_by_tag(&data))
+}
+
+#[tauri::command]
+fn export_folder_merged(app: tauri::AppHandle, folder_id: String) -> Result<String, String> {
+    let data = load_data(&app)?;
+    notes::export_folder_merged_markdown(&data, &folder_id).map_err(|e| format!("export error: {e}"))
+}
+
+#[tauri::command]
+fn export_kanban(app: tauri::AppHandle) -> Result<String, String> {
+    let data = load_data(&app)?;
+    notes::export_kanban_markdown(&data).map_err(|e| format!("export error: {e}"))
+}
+
+#[tauri::command]
+fn find_external_links(app: tauri::AppHandle) -> Result<Vec<(String, Vec<String>)>, String> {
+    let data = load_data(&app)?;
+    Ok(notes::notes_with_urls(&data))
+}
+
+#[tauri::command]
+fn get_backlinks(app: tauri::AppHandle, note_id: String) -> Result<Vec<String>, String> {
+    let data = load_data(&app)?;
+    Ok(notes::find_backlinks(&data, &note_id))
+}
+
+#[tauri::command]
+fn get_backlink_counts(app: tauri::AppHandle) -> Result<Vec<(String, usize)>, String> {
+    let data = load_data(&app)?;
+    Ok(notes::notes_by_backlink_count(&data))
+}
+
+#[tauri::command]
+fn export_closure(app: tauri::AppHandle, note_id: String, max_depth: usize) -> Result<String, String> {
+    let data = load_data(&app)?;
+    notes::export_note_closure_markdown(&data, &note_id, max_depth)
+        .map_err(|e| format!("export error: {e}"))
+}
+
+#[tauri::command]
+fn export_link_graph(app: tauri::AppHandle) -> Result<String, String> {
+    let data = load_data(&app)?;
+    notes::export_graph_dot(&data).map_err(|e| format!("export error: {e}"))
+}
+
+#[tauri::command]
+fn export_date_range(
+    app: tauri::AppHandle,
+    from: u64,
+    to: u64,
+    by: String,
+) -> Result<String, String> {
+    let data = load_data(&app)?;
+    notes::export_range_markdown(&data, from, to, &by).map_err(|e| format!("export error: {e}"))
+}
+
+#[tauri::command]
+fn export_recent(app: tauri::AppHandle, count: usize) -> Result<String, String> {
+    let data = load_data(&app)?;
+    notes::export_recent_markdown(&data, count).map_err(|e| format!("export error: {e}"))
 }
 
 #[tauri::command]
 fn get_data_dir(app: tauri::AppHandle) -> Result<String, String> {
-    let dir = app
-        .path()
-        .app_data_dir()
-        .map_err(|e| format!("app_data_dir error: {e}"))?;
+    let dir = app_data_dir(&app)?;
     Ok(dir.join("parcel").to_string_lossy().to_string())
 }
 
+#[tauri::command]
+fn data_volume_info(app: tauri::AppHandle) -> Result<VolumeInfo, String> {
+    Ok(notes::data_volume_info(app_data_dir(&app)?))
+}
+
+#[tauri::command]
+fn get_stale_notes(app: tauri::AppHandle, older_than_days: u64) -> Result<Vec<Note>, String> {
+    let data = load_data(&app)?;
+    let older_than_ms = older_than_days.saturating_mul(notes::DAY_MS);
+    Ok(notes::stale_notes(&data, now_ms(), older_than_ms))
+}
+
+#[tauri::command]
+fn toggle_note_folder(
+    app: tauri::AppHandle,
+    note_id: String,
+    folder_a: String,
+    folder_b: String,
+) -> Result<String, String> {
+    let mut data = load_data(&app)?;
+    let folder_id = notes::toggle_note_folder(&mut data, &note_id, &folder_a, &folder_b, now_ms())
+        .map_err(|e| format!("move error: {e}"))?;
+    save_data(&app, &mut data)?;
+    Ok(folder_id)
+}
+
+#[tauri::command]
+fn set_id_strategy(app: tauri::AppHandle, strategy: String) -> Result<(), String> {
+    let mut data = load_data(&app)?;
+    notes::set_id_strategy(&mut data, &strategy).map_err(|e| format!("settings error: {e}"))?;
+    save_data(&app, &mut data)
+}
+
+#[tauri::command]
+fn set_default_folder(app: tauri::AppHandle, folder_id: Option<String>) -> Result<(), String> {
+    let mut data = load_data(&app)?;
+    notes::set_default_folder(&mut data, folder_id).map_err(|e| format!("settings error: {e}"))?;
+    save_data(&app, &mut data)
+}
+
+#[tauri::command]
+fn create_note(
+    app: tauri::AppHandle,
+    folder_id: Option<String>,
+    color: Option<String>,
+) -> Result<Note, String> {
+    let mut data = load_data(&app)?;
+    let color = color.unwrap_or_else(|| "paper".to_string());
+    let note = notes::create_note(&mut data, folder_id, &color, now_ms())
+        .map_err(|e| format!("create error: {e}"))?;
+    save_data(&app, &mut data)?;
+    Ok(note)
+}
+
+#[tauri::command]
+fn set_folder_default_tags(
+    app: tauri::AppHandle,
+    folder_id: String,
+    tags: Vec<String>,
+) -> Result<(), String> {
+    let mut data = load_data(&app)?;
+    notes::set_folder_default_tags(&mut data, &folder_id, &tags)
+        .map_err(|e| format!("update error: {e}"))?;
+    save_data(&app, &mut data)
+}
+
+#[tauri::command]
+fn create_notes_from_titles(
+    app: tauri::AppHandle,
+    titles: Vec<String>,
+    folder_id: Option<String>,
+    color: Option<String>,
+) -> Result<Vec<Note>, String> {
+    let mut data = load_data(&app)?;
+    let color = color.unwrap_or_else(|| "paper".to_string());
+    let created = notes::create_notes_batch(&mut data, &titles, folder_id, &color, now_ms(), notes::new_id)
+        .map_err(|e| format!("create error: {e}"))?;
+    save_data(&app, &mut data)?;
+    Ok(created)
+}
+
+#[tauri::command]
+fn get_edit_age_summary(app: tauri::AppHandle) -> Result<EditAgeSummary, String> {
+    let data = load_data(&app)?;
+    Ok(notes::edit_age_summary(&data, now_ms()))
+}
+
+#[tauri::command]
+fn get_word_count_trend(app: tauri::AppHandle) -> Result<Vec<(String, usize)>, String> {
+    let data = load_data(&app)?;
+    Ok(notes::word_count_trend(&data))
+}
+
+#[tauri::command]
+fn get_creation_span(app: tauri::AppHandle) -> Result<Option<(u64, u64)>, String> {
+    let data = load_data(&app)?;
+    Ok(notes::creation_span(&data))
+}
+
+#[tauri::command]
+fn get_edit_streak(app: tauri::AppHandle) -> Result<StreakInfo, String> {
+    let data = load_data(&app)?;
+    Ok(notes::edit_streak(&data, now_ms()))
+}
+
+#[tauri::command]
+fn get_activity_heatmap(app: tauri::AppHandle, year: i32) -> Result<Vec<(String, usize)>, String> {
+    let data = load_data(&app)?;
+    Ok(notes::activity_heatmap(&data, year))
+}
+
+#[tauri::command]
+fn get_folder_stats(app: tauri::AppHandle) -> Result<Vec<FolderStat>, String> {
+    let data = load_data(&app)?;
+    Ok(notes::folder_stats(&data))
+}
+
+#[tauri::command]
+fn get_note_body_lines(
+    app: tauri::AppHandle,
+    note_id: String,
+    start: usize,
+    count: usize,
+) -> Result<Vec<String>, String> {
+    let data = load_data(&app)?;
+    notes::note_body_lines(&data, &note_id, start, count).map_err(|e| format!("read error: {e}"))
+}
+
+#[tauri::command]
+fn diff_json(a: String, b: String) -> Result<DataDiff, String> {
+    notes::diff_json(&a, &b).map_err(|e| format!("read error: {e}"))
+}
+
+#[tauri::command]
+fn get_import_plan(app: tauri::AppHandle, incoming: ParcelData) -> Result<ImportPlan, String> {
+    let data = load_data(&app)?;
+    notes::import_plan(&data, &incoming).map_err(|e| format!("import error: {e}"))
+}
+
+#[tauri::command]
+fn apply_import_plan(
+    app: tauri::AppHandle,
+    incoming: ParcelData,
+    decisions: HashMap<String, ImportDecision>,
+    options: Option<ImportOptions>,
+) -> Result<usize, String> {
+    let mut data = load_data(&app)?;
+    let options = options.unwrap_or_default();
+    let taken = notes::apply_import_plan(&mut data, &incoming, &decisions, &options)
+        .map_err(|e| format!("import error: {e}"))?;
+    if taken > 0 {
+        notes::backup(writable_data_dir(&app)?, now_ms()).map_err(|e| format!("backup error: {e}"))?;
+        save_data(&app, &mut data)?;
+    }
+    Ok(taken)
+}
+
+#[tauri::command]
+fn import_notes_json(
+    app: tauri::AppHandle,
+    incoming: ParcelData,
+    options: Option<ImportOptions>,
+) -> Result<ImportReport, String> {
+    let mut data = load_data(&app)?;
+    let report = notes::import_json(&mut data, &incoming, &options.unwrap_or_default())
+        .map_err(|e| format!("import error: {e}"))?;
+    if report.imported > 0 {
+        notes::backup(writable_data_dir(&app)?, now_ms()).map_err(|e| format!("backup error: {e}"))?;
+        save_data(&app, &mut data)?;
+    }
+    Ok(report)
+}
+
+#[tauri::command]
+fn bulk_replace(
+    app: tauri::AppHandle,
+    pattern: String,
+    replacement: String,
+    note_ids: Option<Vec<String>>,
+) -> Result<usize, String> {
+    let mut data = load_data(&app)?;
+    let now = now_ms();
+    let changed = notes::bulk_replace(&mut data, &pattern, &replacement, note_ids, now)
+        .map_err(|e| format!("replace error: {e}"))?;
+    if changed > 0 {
+        notes::backup(writable_data_dir(&app)?, now).map_err(|e| format!("backup error: {e}"))?;
+        save_data(&app, &mut data)?;
+    }
+    Ok(changed)
+}
+
+#[tauri::command]
+fn preview_replace(
+    app: tauri::AppHandle,
+    pattern: String,
+    note_ids: Option<Vec<String>>,
+) -> Result<Vec<(String, usize)>, String> {
+    let data = load_data(&app)?;
+    notes::preview_bulk_replace(&data, &pattern, note_ids).map_err(|e| format!("replace error: {e}"))
+}
+
+#[tauri::command]
+fn import_txt(
+    app: tauri::AppHandle,
+    text: String,
+    title: Option<String>,
+    folder_id: Option<String>,
+) -> Result<Note, String> {
+    let mut data = load_data(&app)?;
+    let id = notes::new_id(&mut data);
+    let note = notes::import_plaintext(&mut data, &text, title, folder_id, now_ms(), id)
+        .map_err(|e| format!("import error: {e}"))?;
+    save_data(&app, &mut data)?;
+    Ok(note)
+}
+
+#[tauri::command]
+fn suggest_folders(app: tauri::AppHandle, prefix: String) -> Result<Vec<String>, String> {
+    let data = load_data(&app)?;
+    Ok(notes::folder_names(&data, &prefix))
+}
+
+#[tauri::command]
+fn find_predating_notes(app: tauri::AppHandle) -> Result<Vec<String>, String> {
+    let data = load_data(&app)?;
+    Ok(notes::notes_predating_folder(&data))
+}
+
+#[tauri::command]
+fn find_identical_bodies(app: tauri::AppHandle) -> Result<Vec<Vec<String>>, String> {
+    let data = load_data(&app)?;
+    Ok(notes::identical_body_groups(&data))
+}
+
+#[tauri::command]
+fn find_titleless_notes(app: tauri::AppHandle) -> Result<Vec<String>, String> {
+    let data = load_data(&app)?;
+    Ok(notes::titleless_notes(&data))
+}
+
+#[tauri::command]
+fn find_duplicate_folders(app: tauri::AppHandle) -> Result<Vec<(String, Vec<String>)>, String> {
+    let data = load_data(&app)?;
+    Ok(notes::duplicate_folder_names(&data))
+}
+
+#[tauri::command]
+fn find_redundant_titles(app: tauri::AppHandle) -> Result<Vec<String>, String> {
+    let data = load_data(&app)?;
+    Ok(notes::redundant_titles(&data))
+}
+
+#[tauri::command]
+fn get_note_previews(app: tauri::AppHandle, len: usize) -> Result<Vec<(String, String)>, String> {
+    let data = load_data(&app)?;
+    Ok(notes::note_previews(&data, len))
+}
+
+#[tauri::command]
+fn get_folders_union(app: tauri::AppHandle, a: String, b: String) -> Result<Vec<Note>, String> {
+    let data = load_data(&app)?;
+    notes::folder_notes_union(&data, &a, &b).map_err(|e| format!("read error: {e}"))
+}
+
+#[tauri::command]
+fn tag_matching_notes(app: tauri::AppHandle, query: String, tag: String) -> Result<usize, String> {
+    let mut data = load_data(&app)?;
+    let tagged = notes::tag_search_results(&mut data, &query, &tag, now_ms())
+        .map_err(|e| format!("update error: {e}"))?;
+    if tagged > 0 {
+        save_data(&app, &mut data)?;
+    }
+    Ok(tagged)
+}
+
+#[tauri::command]
+fn search_notes_ranked(app: tauri::AppHandle, query: String) -> Result<Vec<(Note, f32)>, String> {
+    let data = load_data(&app)?;
+    Ok(notes::search_ranked(&data, &query))
+}
+
+#[tauri::command]
+fn search_notes_by_title(app: tauri::AppHandle, query: String) -> Result<Vec<Note>, String> {
+    let data = load_data(&app)?;
+    Ok(notes::search_titles(&data, &query))
+}
+
+#[tauri::command]
+fn find_notes_with_attachments(app: tauri::AppHandle) -> Result<Vec<String>, String> {
+    let data = load_data(&app)?;
+    Ok(notes::notes_with_attachments(&data))
+}
+
+#[tauri::command]
+fn get_folders_by_activity(app: tauri::AppHandle) -> Result<Vec<Folder>, String> {
+    let data = load_data(&app)?;
+    Ok(notes::folders_by_activity(&data))
+}
+
+#[tauri::command]
+fn get_reading_times(
+    app: tauri::AppHandle,
+    wpm: Option<usize>,
+) -> Result<HashMap<String, usize>, String> {
+    let data = load_data(&app)?;
+    let wpm = wpm.unwrap_or(notes::DEFAULT_WPM);
+    Ok(data
+        .notes
+        .iter()
+        .map(|n| (n.id.clone(), notes::reading_time(n, wpm)))
+        .collect())
+}
+
+#[tauri::command]
+fn validate_note(note: Note) -> Result<Vec<String>, String> {
+    Ok(notes::validate_note(&note))
+}
+
+#[tauri::command]
+fn convert_storage_layout(app: tauri::AppHandle, to: String) -> Result<(), String> {
+    notes::convert_storage_layout(writable_data_dir(&app)?, &to, now_ms())
+        .map_err(|e| format!("convert error: {e}"))
+}
+
+#[tauri::command]
+fn update_note(
+    app: tauri::AppHandle,
+    note_id: String,
+    title: Option<String>,
+    body: Option<String>,
+) -> Result<Note, String> {
+    let mut data = load_data(&app)?;
+    let note = notes::update_note(&mut data, &note_id, title, body, now_ms())
+        .map_err(|e| format!("update error: {e}"))?;
+    log_edit(&app, &note)?;
+    Ok(note)
+}
+
+#[tauri::command]
+fn patch_note(app: tauri::AppHandle, note_id: String, patch: NotePatch) -> Result<Note, String> {
+    let mut data = load_data(&app)?;
+    let note = notes::patch_note(&mut data, &note_id, patch, now_ms())
+        .map_err(|e| format!("update error: {e}"))?;
+    log_edit(&app, &note)?;
+    Ok(note)
+}
+
+#[tauri::command]
+fn append_to_note(app: tauri::AppHandle, note_id: String, text: String) -> Result<Note, String> {
+    let mut data = load_data(&app)?;
+    let note = notes::append_to_note(&mut data, &note_id, &text, now_ms())
+        .map_err(|e| format!("update error: {e}"))?;
+    log_edit(&app, &note)?;
+    Ok(note)
+}
+
+#[tauri::command]
+fn set_max_body_len(app: tauri::AppHandle, len: Option<usize>) -> Result<(), String> {
+    let mut data = load_data(&app)?;
+    data.max_body_len = len;
+    save_data(&app, &mut data)
+}
+
+#[tauri::command]
+fn get_outline(app: tauri::AppHandle) -> Result<Vec<OutlineEntry>, String> {
+    let data = load_data(&app)?;
+    Ok(notes::outline(&data))
+}
+
+#[tauri::command]
+fn get_note_extremes(app: tauri::AppHandle) -> Result<(Option<String>, Option<String>), String> {
+    let data = load_data(&app)?;
+    Ok(notes::note_extremes(&data))
+}
+
+#[tauri::command]
+fn delete_notes(
+    app: tauri::AppHandle,
+    note_ids: Vec<String>,
+    force: Option<bool>,
+) -> Result<DeleteReport, String> {
+    let mut data = load_data(&app)?;
+    let report = notes::delete_notes(&mut data, &note_ids, force.unwrap_or(false));
+    save_data(&app, &mut data)?;
+    Ok(report)
+}
+
+#[tauri::command]
+fn delete_note(
+    app: tauri::AppHandle,
+    note_id: String,
+    force: Option<bool>,
+) -> Result<DeleteReport, String> {
+    delete_notes(app, vec![note_id], force)
+}
+
+#[tauri::command]
+fn set_protected(app: tauri::AppHandle, note_id: String, protected: bool) -> Result<(), String> {
+    let mut data = load_data(&app)?;
+    notes::set_protected(&mut data, &note_id, protected).map_err(|e| format!("update error: {e}"))?;
+    save_data(&app, &mut data)
+}
+
+#[tauri::command]
+fn set_pinned(app: tauri::AppHandle, note_id: String, pinned: bool) -> Result<(), String> {
+    let mut data = load_data(&app)?;
+    notes::set_pinned(&mut data, &note_id, pinned, now_ms()).map_err(|e| format!("update error: {e}"))?;
+    save_data(&app, &mut data)
+}
+
+#[tauri::command]
+fn set_color_locked(app: tauri::AppHandle, note_id: String, locked: bool) -> Result<(), String> {
+    let mut data = load_data(&app)?;
+    notes::set_color_locked(&mut data, &note_id, locked).map_err(|e| format!("update error: {e}"))?;
+    save_data(&app, &mut data)
+}
+
+#[tauri::command]
+fn recolor_all(app: tauri::AppHandle, color: String) -> Result<usize, String> {
+    let mut data = load_data(&app)?;
+    let changed =
+        notes::recolor_all(&mut data, &color, now_ms()).map_err(|e| format!("update error: {e}"))?;
+    save_data(&app, &mut data)?;
+    Ok(changed)
+}
+
+#[tauri::command]
+fn recolor_folder_notes(app: tauri::AppHandle, folder_id: String, color: String) -> Result<usize, String> {
+    let mut data = load_data(&app)?;
+    let changed = notes::recolor_folder_notes(&mut data, &folder_id, &color, now_ms())
+        .map_err(|e| format!("update error: {e}"))?;
+    save_data(&app, &mut data)?;
+    Ok(changed)
+}
+
+#[tauri::command]
+fn color_notes_by_tag(app: tauri::AppHandle, tag: String, color: String) -> Result<usize, String> {
+    let mut data = load_data(&app)?;
+    let changed = notes::color_notes_by_tag(&mut data, &tag, &color, now_ms())
+        .map_err(|e| format!("update error: {e}"))?;
+    save_data(&app, &mut data)?;
+    Ok(changed)
+}
+
+#[tauri::command]
+fn lint_markdown(app: tauri::AppHandle) -> Result<Vec<(String, Vec<String>)>, String> {
+    let data = load_data(&app)?;
+    Ok(notes::notes_with_markdown_issues(&data))
+}
+
+#[tauri::command]
+fn find_code_notes(app: tauri::AppHandle) -> Result<Vec<String>, String> {
+    let data = load_data(&app)?;
+    Ok(notes::notes_with_code(&data))
+}
+
+#[tauri::command]
+fn get_code_snippets(app: tauri::AppHandle) -> Result<Vec<(String, String, String)>, String> {
+    let data = load_data(&app)?;
+    notes::export_snippets(&data).map_err(|e| format!("export error: {e}"))
+}
+
+#[tauri::command]
+fn get_code_languages(app: tauri::AppHandle) -> Result<HashMap<String, Vec<String>>, String> {
+    let data = load_data(&app)?;
+    Ok(notes::code_languages(&data))
+}
+
+#[tauri::command]
+fn archive_old(app: tauri::AppHandle, older_than_days: u64) -> Result<usize, String> {
+    let mut data = load_data(&app)?;
+    let older_than_ms = older_than_days.saturating_mul(notes::DAY_MS);
+    let archived = notes::archive_old_notes(&mut data, now_ms(), older_than_ms);
+    if archived > 0 {
+        save_data(&app, &mut data)?;
+    }
+    Ok(archived)
+}
+
+#[tauri::command]
+fn set_folder_archived(app: tauri::AppHandle, folder_id: String, archived: bool) -> Result<(), String> {
+    let mut data = load_data(&app)?;
+    notes::set_folder_archived(&mut data, &folder_id, archived, now_ms())
+        .map_err(|e| format!("update error: {e}"))?;
+    save_data(&app, &mut data)
+}
+
+#[tauri::command]
+fn find_notes_in_archived_folders(app: tauri::AppHandle) -> Result<Vec<String>, String> {
+    let data = load_data(&app)?;
+    Ok(notes::notes_in_archived_folders(&data))
+}
+
+#[tauri::command]
+fn fix_notes_in_archived_folders(app: tauri::AppHandle, fix: ArchivedFolderFix) -> Result<usize, String> {
+    let mut data = load_data(&app)?;
+    let fixed = notes::fix_notes_in_archived_folders(&mut data, fix, now_ms());
+    if fixed > 0 {
+        save_data(&app, &mut data)?;
+    }
+    Ok(fixed)
+}
+
+#[tauri::command]
+fn import_notes_csv(app: tauri::AppHandle, csv: String) -> Result<usize, String> {
+    let mut data = load_data(&app)?;
+    let imported = notes::import_csv(&mut data, &csv, now_ms(), notes::new_id)
+        .map_err(|e| format!("import error: {e}"))?;
+    save_data(&app, &mut data)?;
+    Ok(imported)
+}
+
+#[tauri::command]
+fn import_notes_markdown(app: tauri::AppHandle, markdown: String) -> Result<usize, String> {
+    let mut data = load_data(&app)?;
+    let imported = notes::import_markdown(&mut data, &markdown, now_ms(), notes::new_id)
+        .map_err(|e| format!("import error: {e}"))?;
+    save_data(&app, &mut data)?;
+    Ok(imported)
+}
+
+#[tauri::command]
+fn trim_bodies(app: tauri::AppHandle) -> Result<usize, String> {
+    let mut data = load_data(&app)?;
+    let changed = notes::trim_trailing_whitespace(&mut data);
+    if changed > 0 {
+        save_data(&app, &mut data)?;
+    }
+    Ok(changed)
+}
+
+#[tauri::command]
+fn find_duplicate_tags(app: tauri::AppHandle) -> Result<Vec<String>, String> {
+    let data = load_data(&app)?;
+    Ok(notes::notes_with_duplicate_tags(&data))
+}
+
+#[tauri::command]
+fn dedupe_tags(app: tauri::AppHandle) -> Result<usize, String> {
+    let mut data = load_data(&app)?;
+    let changed = notes::dedupe_note_tags(&mut data);
+    if changed > 0 {
+        save_data(&app, &mut data)?;
+    }
+    Ok(changed)
+}
+
+#[tauri::command]
+fn normalize_tags(app: tauri::AppHandle) -> Result<usize, String> {
+    let mut data = load_data(&app)?;
+    let changed = notes::normalize_all_tags(&mut data);
+    if changed > 0 {
+        save_data(&app, &mut data)?;
+    }
+    Ok(changed)
+}
+
+#[tauri::command]
+fn get_note_position(
+    app: tauri::AppHandle,
+    note_id: String,
+    order: SortOrder,
+) -> Result<(usize, usize), String> {
+    let data = load_data(&app)?;
+    notes::note_position(&data, &note_id, order).map_err(|e| format!("read error: {e}"))
+}
+
+// Not a secret: the hint is stored unencrypted and must not be the passphrase
+#[tauri::command]
+fn set_passphrase_hint(app: tauri::AppHandle, hint: String) -> Result<(), String> {
+    notes::write_passphrase_hint(writable_data_dir(&app)?, &hint)
+        .map_err(|e| format!("hint error: {e}"))
+}
+
+#[tauri::command]
+fn get_passphrase_hint(app: tauri::AppHandle) -> Result<Option<String>, String> {
+    notes::read_passphrase_hint(app_data_dir(&app)?).map_err(|e| format!("hint error: {e}"))
+}
+
+// Returns the backup's file name
+#[tauri::command]
+fn backup_now(app: tauri::AppHandle) -> Result<String, String> {
+    let path = notes::backup(writable_data_dir(&app)?, now_ms())
+        .map_err(|e| format!("backup error: {e}"))?
+        .ok_or_else(|| "backup error: there is no saved data to back up yet".to_string())?;
+    Ok(path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default())
+}
+
+#[tauri::command]
+fn clean_backups(app: tauri::AppHandle) -> Result<usize, String> {
+    notes::clean_backups(writable_data_dir(&app)?).map_err(|e| format!("backup error: {e}"))
+}
+
+#[tauri::command]
+fn set_backup_interval(app: tauri::AppHandle, ms: u64) -> Result<(), String> {
+    let mut data = load_data(&app)?;
+    data.backup_min_interval_ms = ms;
+    save_data(&app, &mut data)
+}
+
+#[tauri::command]
+fn set_pinned_save_version(app: tauri::AppHandle, version: Option<u32>) -> Result<(), String> {
+    let mut data = load_data(&app)?;
+    notes::set_pinned_save_version(&mut data, version).map_err(|e| format!("settings error: {e}"))?;
+    save_data(&app, &mut data)
+}
+
+// Switch between minified and pretty data files, rewriting them right away
+#[tauri::command]
+fn save_minified(app: tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    let mut data = load_data(&app)?;
+    data.minify_json = enabled;
+    save_data(&app, &mut data)
+}
+
+#[tauri::command]
+fn set_touch_on_metadata(app: tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    let mut data = load_data(&app)?;
+    data.touch_on_metadata = enabled;
+    save_data(&app, &mut data)
+}
+
+#[tauri::command]
+fn set_trailing_newline(app: tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    let mut data = load_data(&app)?;
+    data.trailing_newline = enabled;
+    save_data(&app, &mut data)
+}
+
+#[tauri::command]
+fn set_auto_recover(app: tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    let mut data = load_data(&app)?;
+    data.auto_recover = enabled;
+    save_data(&app, &mut data)
+}
+
+#[tauri::command]
+fn set_auto_title(app: tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    let mut data = load_data(&app)?;
+    data.auto_title_from_body = enabled;
+    save_data(&app, &mut data)
+}
+
+// Saving applies the new limit to existing revisions right away
+#[tauri::command]
+fn set_max_revisions(app: tauri::AppHandle, n: u32) -> Result<(), String> {
+    let mut data = load_data(&app)?;
+    data.max_revisions = n;
+    save_data(&app, &mut data)
+}
+
+#[tauri::command]
+fn set_untitled_placeholder(app: tauri::AppHandle, text: String) -> Result<(), String> {
+    let mut data = load_data(&app)?;
+    notes::set_untitled_placeholder(&mut data, &text).map_err(|e| format!("update error: {e}"))?;
+    save_data(&app, &mut data)
+}
+
 fn main() {
     tauri::Builder::default()
+        .manage(ReadOnlySession::default())
+        .manage(RecoveredFromBackup::default())
         .invoke_handler(tauri::generate_handler![
-            load_notes, 
+            load_notes,
+            get_load_diagnostics,
+            get_save_seq,
             save_notes,
+            log_note_edit,
+            replay_wal,
+            pending_changes,
+            flush_now,
+            export_notes,
+            export_bundle,
+            import_bundle,
             export_notes_json,
+            export_json_version,
+            export_folders_json,
             export_notes_markdown,
-            get_data_dir
+            export_notes_docx,
+            export_notes_org,
+            set_reminder,
+            export_reminders_ics,
+            export_tags,
+            export_kanban,
+            get_notes_by_tag,
+            export_link_graph,
+            find_external_links,
+            export_date_range,
+            export_recent,
+            get_data_dir,
+            data_volume_info,
+            get_stale_notes,
+            toggle_note_folder,
+            set_id_strategy,
+            set_default_folder,
+            create_note,
+            create_notes_from_titles,
+            set_folder_default_tags,
+            get_activity_heatmap,
+            get_edit_streak,
+            get_edit_age_summary,
+            get_folder_stats,
+            get_note_body_lines,
+            get_import_plan,
+            apply_import_plan,
+            bulk_replace,
+            preview_replace,
+            import_txt,
+            search_notes_by_title,
+            find_notes_with_attachments,
+            get_folders_union,
+            get_note_previews,
+            find_redundant_titles,
+            find_titleless_notes,
+            get_folders_by_activity,
+            suggest_folders,
+            get_reading_times,
+            validate_note,
+            convert_storage_layout,
+            update_note,
+            patch_note,
+            append_to_note,
+            set_max_body_len,
+            get_outline,
+            get_note_extremes,
+            delete_notes,
+            delete_note,
+            set_protected,
+            set_pinned,
+            find_code_notes,
+            get_code_languages,
+            get_code_snippets,
+            archive_old,
+            set_folder_archived,
+            find_notes_in_archived_folders,
+            fix_notes_in_archived_folders,
+            import_notes_csv,
+            import_notes_markdown,
+            normalize_tags,
+            trim_bodies,
+            get_note_position,
+            set_backup_interval,
+            clean_backups,
+            backup_now,
+            set_passphrase_hint,
+            get_passphrase_hint,
+            set_untitled_placeholder,
+            set_auto_title,
+            set_max_revisions,
+            set_pinned_save_version,
+            save_minified,
+            set_color_locked,
+            recolor_all,
+            recolor_folder_notes,
+            color_notes_by_tag,
+            find_duplicate_folders,
+            export_by_date_tree,
+            get_schema_fields,
+            set_auto_recover,
+            diff_json,
+            find_predating_notes,
+            export_folder_merged,
+            import_notes_json,
+            get_export_size_estimate,
+            get_backlinks,
+            get_backlink_counts,
+            export_closure,
+            find_duplicate_tags,
+            dedupe_tags,
+            set_trailing_newline,
+            get_creation_span,
+            search_notes_ranked,
+            export_notes_yaml,
+            import_notes_yaml,
+            tag_matching_notes,
+            open_readonly,
+            close_readonly,
+            is_read_only,
+            lint_markdown,
+            export_notes_tree_zip,
+            find_identical_bodies,
+            set_touch_on_metadata,
+            get_word_count_trend,
+            export_notes_pdf
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
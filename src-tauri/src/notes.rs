@@ -1,10 +1,83 @@
+use chrono::{DateTime, Datelike, NaiveDate};
+use regex::{Regex, RegexBuilder};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+pub const CURRENT_VERSION: u32 = 2;
 
+// Fields introduced by each schema version after the first, as
+// (version, note fields, top-level fields), using their serialized names
+const SCHEMA_ADDITIONS: &[(u32, &[&str], &[&str])] = &[(2, &["tags", "protected", "archived", "pinnedAt", "attachments", "revisions", "reminderAt", "colorLocked"], &["archived"])];
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// Serialized (name, JSON type, required) for every field as of the current version;
+// fields in SCHEMA_ADDITIONS are left out of versions before the one that added them
+const NOTE_FIELDS: &[(&str, &str, bool)] = &[
+    ("id", "string", true),
+    ("title", "string", true),
+    ("body", "string", true),
+    ("folderId", "string|null", false),
+    ("pinned", "boolean", true),
+    ("color", "string", true),
+    ("createdAt", "number", true),
+    ("updatedAt", "number", true),
+    ("tags", "array", false),
+    ("protected", "boolean", false),
+    ("archived", "boolean", false),
+    ("pinnedAt", "number|null", false),
+    ("attachments", "array", false),
+    ("revisions", "array", false),
+    ("reminderAt", "number|null", false),
+    ("colorLocked", "boolean", false),
+];
+
+const FOLDER_FIELDS: &[(&str, &str, bool)] = &[
+    ("id", "string", true),
+    ("name", "string", true),
+    ("createdAt", "number", true),
+    ("updatedAt", "number", true),
+    ("archived", "boolean", false),
+];
+
+pub const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+pub const NOTE_COLORS: [&str; 6] = ["paper", "yellow", "mint", "lavender", "salmon", "sky"];
+
+pub const ID_STRATEGIES: [&str; 2] = ["uuid", "counter"];
+
+// "single" keeps everything in notes.json; "split" writes one file per note
+pub const STORAGE_LAYOUTS: [&str; 2] = ["single", "split"];
+
+pub const DEFAULT_WPM: usize = 200;
+
+// Longest title `auto_title_from_body` will generate, in characters
+const AUTO_TITLE_MAX_CHARS: usize = 80;
+
+// Shown in exports for notes with an empty title unless configured otherwise
+const DEFAULT_UNTITLED: &str = "Untitled";
+
+const MAX_BACKUPS: usize = 10;
+
+// Bodies above this size get a warning from `validate_note`
+const LARGE_BODY_BYTES: usize = 1024 * 1024;
+
+// Upper bound on the compiled size of user-supplied patterns
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+const MAX_FOLDER_SUGGESTIONS: usize = 20;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldInfo {
+    // "note" or "folder"
+    pub entity: String,
+    pub name: String,
+    pub json_type: String,
+    pub required: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Folder {
     pub id: String,
     pub name: String,
@@ -12,9 +85,11 @@ pub struct Folder {
     pub created_at: u64,
     #[serde(rename = "updatedAt")]
     pub updated_at: u64,
+    #[serde(default)]
+    pub archived: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Note {
     pub id: String,
@@ -25,47 +100,638 @@ pub struct Note {
     pub color: String,
     pub created_at: u64,
     pub updated_at: u64,
+    // Added in version 2
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // Skipped by deletes unless forced
+    #[serde(default)]
+    pub protected: bool,
+    #[serde(default)]
+    pub archived: bool,
+    // When the note was last pinned; None while unpinned
+    #[serde(default)]
+    pub pinned_at: Option<u64>,
+    // File names of the files attached to the note
+    #[serde(default)]
+    pub attachments: Vec<String>,
+    // Earlier versions of the title and body, oldest first. Owned by the
+    // backend: saves and WAL replays record them, whatever the frontend sends.
+    #[serde(default)]
+    pub revisions: Vec<NoteRevision>,
+    // When to be reminded of the note, epoch ms
+    #[serde(default)]
+    pub reminder_at: Option<u64>,
+    // Kept out of bulk recolors
+    #[serde(default)]
+    pub color_locked: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// A title and body a later edit replaced
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteRevision {
+    pub title: String,
+    pub body: String,
+    // When this version was written
+    pub updated_at: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ParcelData {
     pub version: u32,
     pub notes: Vec<Note>,
     pub folders: Vec<Folder>,
+    // "uuid" or "counter" (note-0042 style ids)
+    #[serde(default = "default_id_strategy")]
+    pub id_strategy: String,
+    #[serde(default = "default_next_counter")]
+    pub next_counter: u64,
+    // Folder new notes land in when none is given
+    #[serde(default)]
+    pub default_folder_id: Option<String>,
+    #[serde(default = "default_storage_layout")]
+    pub storage_layout: String,
+    // Hard cap on body length in characters; None means unlimited
+    #[serde(default)]
+    pub max_body_len: Option<usize>,
+    // Routine saves back up at most this often
+    #[serde(default = "default_backup_min_interval_ms")]
+    pub backup_min_interval_ms: u64,
+    // Incremented by every successful save, for external sync reconciliation
+    #[serde(default)]
+    pub save_seq: u64,
+    // Stands in for empty titles in exports
+    #[serde(default = "default_untitled_placeholder")]
+    pub untitled_placeholder: String,
+    // Tags given to notes created in a folder, by folder id
+    #[serde(default)]
+    pub folder_default_tags: HashMap<String, Vec<String>>,
+    // Fill empty titles from the body's first line on save
+    #[serde(default)]
+    pub auto_title_from_body: bool,
+    // Revisions kept per note; saves drop the oldest beyond this
+    #[serde(default = "default_max_revisions")]
+    pub max_revisions: u32,
+    // Saves write this older format version instead of the current one
+    #[serde(default)]
+    pub pinned_save_version: Option<u32>,
+    // Write data files without pretty-printing
+    #[serde(default)]
+    pub minify_json: bool,
+    // Load the newest good backup when notes.json is corrupt. The corrupt file
+    // can't say whether this is on, so the backup's own copy of it decides.
+    #[serde(default)]
+    pub auto_recover: bool,
+    // End data files with a newline
+    #[serde(default)]
+    pub trailing_newline: bool,
+    // Bump updated_at for pin, color, folder, and tag changes, not just title
+    // and body edits
+    #[serde(default = "default_touch_on_metadata")]
+    pub touch_on_metadata: bool,
+}
+
+fn default_untitled_placeholder() -> String {
+    DEFAULT_UNTITLED.to_string()
+}
+
+fn default_max_revisions() -> u32 {
+    20
+}
+
+fn default_touch_on_metadata() -> bool {
+    true
+}
+
+fn default_id_strategy() -> String {
+    "uuid".to_string()
+}
+
+fn default_next_counter() -> u64 {
+    1
+}
+
+fn default_backup_min_interval_ms() -> u64 {
+    60 * 60 * 1000
+}
+
+fn default_storage_layout() -> String {
+    "single".to_string()
+}
+
+impl ParcelData {
+    pub fn empty() -> Self {
+        ParcelData {
+            version: CURRENT_VERSION,
+            notes: Vec::new(),
+            folders: Vec::new(),
+            id_strategy: default_id_strategy(),
+            next_counter: default_next_counter(),
+            default_folder_id: None,
+            storage_layout: default_storage_layout(),
+            max_body_len: None,
+            backup_min_interval_ms: default_backup_min_interval_ms(),
+            save_seq: 0,
+            untitled_placeholder: default_untitled_placeholder(),
+            folder_default_tags: HashMap::new(),
+            auto_title_from_body: false,
+            max_revisions: default_max_revisions(),
+            pinned_save_version: None,
+            minify_json: false,
+            auto_recover: false,
+            trailing_newline: false,
+            touch_on_metadata: default_touch_on_metadata(),
+        }
+    }
+}
+
+// Settings are owned by the backend; the frontend only sends notes and folders,
+// so keep whatever is stored on disk when it saves.
+pub fn carry_over_settings(data: &mut ParcelData, stored: &ParcelData) {
+    data.id_strategy = stored.id_strategy.clone();
+    data.next_counter = stored.next_counter;
+    data.default_folder_id = stored.default_folder_id.clone();
+    data.storage_layout = stored.storage_layout.clone();
+    data.max_body_len = stored.max_body_len;
+    data.backup_min_interval_ms = stored.backup_min_interval_ms;
+    data.save_seq = stored.save_seq;
+    data.untitled_placeholder = stored.untitled_placeholder.clone();
+    data.folder_default_tags = stored.folder_default_tags.clone();
+    data.auto_title_from_body = stored.auto_title_from_body;
+    data.max_revisions = stored.max_revisions;
+    data.pinned_save_version = stored.pinned_save_version;
+    data.minify_json = stored.minify_json;
+    data.auto_recover = stored.auto_recover;
+    data.trailing_newline = stored.trailing_newline;
+    data.touch_on_metadata = stored.touch_on_metadata;
+}
+
+// Same options as the note list; pinned notes always come first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    // Most recently updated first
+    Updated,
+    // Most recently created first
+    Created,
+    // Alphabetical
+    Title,
+}
+
+// Notes per calendar bucket of their last edit (UTC). Each note lands in the
+// first bucket that fits, so `this_week` excludes today and so on.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditAgeSummary {
+    pub today: usize,
+    // Same ISO week (Monday to Sunday)
+    pub this_week: usize,
+    pub this_month: usize,
+    pub this_year: usize,
+    pub older: usize,
+}
+
+// Runs of consecutive UTC days with at least one note update
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreakInfo {
+    // Ends today or yesterday; 0 once a whole day has been missed
+    pub current_streak: usize,
+    pub longest_streak: usize,
+    // YYYY-MM-DD
+    pub last_edit_day: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderStat {
+    // None for the folderless bucket
+    pub folder_id: Option<String>,
+    pub name: String,
+    pub note_count: usize,
+    pub word_count: usize,
+    pub pinned_count: usize,
+    // Most recent note update, None for an empty folder
+    pub last_updated: Option<u64>,
+}
+
+// How an incoming note relates to the local data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportStatus {
+    // No local note has this id
+    New,
+    Identical,
+    // Same id, different content
+    Conflict,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportPlanEntry {
+    pub id: String,
+    pub title: String,
+    pub status: ImportStatus,
+}
+
+// Incoming notes in their original order, each with its status
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportPlan {
+    pub entries: Vec<ImportPlanEntry>,
+}
+
+// How incoming notes are merged into the local data
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ImportOptions {
+    // Notes that replace a local one keep the union of both notes' tags
+    pub merge_tags: bool,
+    // Give incoming folders ids derived from their names (`folder_id_from_name`),
+    // so importing the same source again reuses its folders
+    pub folder_ids_from_names: bool,
+    // Give incoming notes fresh ids, and folders too unless they get name ids,
+    // rewriting folder references and [[id]] links to match
+    pub regenerate_ids: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    pub imported: usize,
+    // Old id -> new id for every note and folder `regenerate_ids` renamed
+    pub id_map: HashMap<String, String>,
+}
+
+// Namespace for `folder_id_from_name`; changing it would orphan folders from
+// earlier imports
+const FOLDER_ID_NAMESPACE: uuid::Uuid = uuid::Uuid::from_u128(0x6f3c_2a1e_9b4d_4c7a_8e21_5d0f_b7a9_c413);
+
+// A UUIDv5 of the trimmed, lowercased name, the same on every machine
+pub fn folder_id_from_name(name: &str) -> String {
+    uuid::Uuid::new_v5(&FOLDER_ID_NAMESPACE, name.trim().to_lowercase().as_bytes()).to_string()
+}
+
+// Ids that differ between two data sets; changed means same id, different content hash
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DataDiff {
+    pub added_notes: Vec<String>,
+    pub removed_notes: Vec<String>,
+    pub changed_notes: Vec<String>,
+    pub added_folders: Vec<String>,
+    pub removed_folders: Vec<String>,
+    pub changed_folders: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportDecision {
+    KeepLocal,
+    TakeIncoming,
+    Skip,
+}
+
+// Fields to change on a note; None leaves a field as it is. `folder_id` is
+// Some(None) to move the note out of its folder.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NotePatch {
+    pub title: Option<String>,
+    pub body: Option<String>,
+    pub color: Option<String>,
+    #[serde(deserialize_with = "present")]
+    pub folder_id: Option<Option<String>>,
+    pub pinned: Option<bool>,
+    pub tags: Option<Vec<String>>,
+}
+
+// Tells an explicit null (Some(None)) apart from a missing field (None)
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteReport {
+    pub deleted: usize,
+    // Protected notes left in place
+    pub skipped: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutlineEntry {
+    pub id: String,
+    pub title: String,
+    pub depth: usize,
+    // "folder" or "note"
+    pub kind: String,
+}
+
+// Errors callers may want to tell apart from general failures
+#[derive(Debug)]
+pub enum ParcelError {
+    TooLarge { len: usize, max: usize },
+    // The data was opened with `load_read_only` and must not be written
+    ReadOnly,
+    // Imported data is from a newer app version than this one understands
+    UnsupportedVersion { found: u32, supported: u32 },
+}
+
+impl std::fmt::Display for ParcelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParcelError::TooLarge { len, max } => write!(
+                f,
+                "Note body is {} characters, over the limit of {}",
+                len, max
+            ),
+            ParcelError::ReadOnly => write!(f, "Data is open read-only; nothing can be changed"),
+            ParcelError::UnsupportedVersion { found, supported } => write!(
+                f,
+                "Data is format version {}, but this version of Parcel only supports up to {}",
+                found, supported
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParcelError {}
+
+// Options shared by the document exports
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ExportOptions {
+    // Emitted verbatim at the start/end of the document
+    pub header: Option<String>,
+    pub footer: Option<String>,
+    // Include the body word count in each note's metadata line
+    pub word_count: bool,
+    // Emitted between consecutive notes of a folder, e.g. "---"
+    pub separator: Option<String>,
+    // Markdown only: an `<!-- id: ... -->` line under each heading, so
+    // `import_markdown` can update the same notes later
+    pub include_ids: bool,
+    // Cut bodies to this many characters, marked with TRUNCATED_MARKER.
+    // Applies to markdown, HTML, and plaintext.
+    pub body_limit: Option<usize>,
+    // Markdown and HTML only: leave pinned notes out
+    pub exclude_pinned: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions {
+            header: None,
+            footer: None,
+            word_count: true,
+            separator: None,
+            include_ids: false,
+            body_limit: None,
+            exclude_pinned: false,
+        }
+    }
+}
+
+const TRUNCATED_MARKER: &str = "…(truncated)";
+
+// The body as the document exports show it, cut to `options.body_limit` chars
+fn export_body<'a>(note: &'a Note, options: &ExportOptions) -> std::borrow::Cow<'a, str> {
+    match options.body_limit {
+        Some(limit) => match note.body.char_indices().nth(limit) {
+            Some((end, _)) => format!("{}{}", &note.body[..end], TRUNCATED_MARKER).into(),
+            None => note.body.as_str().into(),
+        },
+        None => note.body.as_str().into(),
+    }
+}
+
+// The data a document export works from: everything, or without pinned notes
+// when `exclude_pinned` is set
+fn export_data<'a>(data: &'a ParcelData, options: &ExportOptions) -> std::borrow::Cow<'a, ParcelData> {
+    if !options.exclude_pinned {
+        return std::borrow::Cow::Borrowed(data);
+    }
+    let mut unpinned = data.clone();
+    unpinned.notes.retain(|n| !n.pinned);
+    std::borrow::Cow::Owned(unpinned)
 }
 
 fn data_file(app_data_dir: PathBuf) -> PathBuf {
     app_data_dir.join("parcel").join("notes.json")
 }
 
+// Append-only journal of note edits made since the last full save
+fn wal_file(app_data_dir: PathBuf) -> PathBuf {
+    app_data_dir.join("parcel").join("wal.log")
+}
+
+fn backups_dir(app_data_dir: PathBuf) -> PathBuf {
+    app_data_dir.join("parcel").join("backups")
+}
+
+// Split layout: folders and settings in folders.json, notes under notes/<id>.json
+fn folders_file(app_data_dir: PathBuf) -> PathBuf {
+    app_data_dir.join("parcel").join("folders.json")
+}
+
+fn split_notes_dir(app_data_dir: PathBuf) -> PathBuf {
+    app_data_dir.join("parcel").join("notes")
+}
+
+fn hint_file(app_data_dir: PathBuf) -> PathBuf {
+    app_data_dir.join("parcel").join("hint.txt")
+}
+
+// The passphrase hint lives in hint.txt as plain text. It is never encrypted,
+// so it must only ever hold a reminder of the passphrase, not the passphrase
+// itself. An empty hint removes the file.
+pub fn write_passphrase_hint(app_data_dir: PathBuf, hint: &str) -> anyhow::Result<()> {
+    let path = hint_file(app_data_dir);
+    let hint = hint.trim();
+    if hint.is_empty() {
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, hint)?;
+    Ok(())
+}
+
+// The hint from `write_passphrase_hint`, or None if none is set
+pub fn read_passphrase_hint(app_data_dir: PathBuf) -> anyhow::Result<Option<String>> {
+    let path = hint_file(app_data_dir);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let hint = fs::read_to_string(path)?;
+    Ok(Some(hint.trim().to_string()).filter(|h| !h.is_empty()))
+}
+
 pub fn load(app_data_dir: PathBuf) -> anyhow::Result<ParcelData> {
-    let path = data_file(app_data_dir);
+    load_with_diagnostics(app_data_dir).map(|(data, _)| data)
+}
+
+// What `load` had to work around in the files on disk
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadDiagnostics {
+    // notes.json started with a UTF-8 byte order mark, which was ignored
+    pub bom_stripped: bool,
+    // notes.json couldn't be parsed and was replaced by the newest good backup;
+    // the corrupt file was kept as notes.json.corrupt
+    pub recovered_from_backup: bool,
+}
+
+// Editors on Windows like to prepend a BOM, which serde_json rejects
+fn strip_bom(s: &str) -> (&str, bool) {
+    match s.strip_prefix('\u{feff}') {
+        Some(rest) => (rest, true),
+        None => (s, false),
+    }
+}
+
+pub fn load_with_diagnostics(app_data_dir: PathBuf) -> anyhow::Result<(ParcelData, LoadDiagnostics)> {
+    load_from(app_data_dir, true)
+}
+
+// Load without writing anything: a corrupt file is an error even with
+// auto_recover on. Unlike `load_with_wal`, edits still sitting in the WAL
+// don't show.
+pub fn load_read_only(app_data_dir: PathBuf) -> anyhow::Result<(ParcelData, LoadDiagnostics)> {
+    load_from(app_data_dir, false)
+}
+
+fn load_from(app_data_dir: PathBuf, recover: bool) -> anyhow::Result<(ParcelData, LoadDiagnostics)> {
+    let path = data_file(app_data_dir.clone());
+    let mut diagnostics = LoadDiagnostics::default();
     
     // Check if file exists
-    if !path.exists() {
+    let data = if path.exists() {
+        let s = fs::read_to_string(&path)?;
+        let (s, bom) = strip_bom(&s);
+        diagnostics.bom_stripped = bom;
+
+        // Try to parse JSON, with better error handling
+        match serde_json::from_str(s) {
+            Ok(data) => data,
+            Err(e) => {
+                let recovered = if recover {
+                    recover_from_backup(app_data_dir.clone())?
+                } else {
+                    None
+                };
+                let Some(data) = recovered else {
+                    return Err(anyhow::anyhow!("Failed to parse JSON: {}. File may be corrupt.", e));
+                };
+                diagnostics.recovered_from_backup = true;
+                data
+            }
+        }
+    } else if folders_file(app_data_dir.clone()).exists() {
+        read_split(app_data_dir)?
+    } else {
         // Return empty data structure for first run
-        return Ok(ParcelData {
-            version: 1,
-            notes: Vec::new(),
-            folders: Vec::new(),
-        });
-    }
-    
-    let s = fs::read_to_string(&path)?;
-    
-    // Try to parse JSON, with better error handling
-    let mut data: ParcelData = serde_json::from_str(&s)
-        .map_err(|e| anyhow::anyhow!("Failed to parse JSON: {}. File may be corrupt.", e))?;
+        return Ok((ParcelData::empty(), diagnostics));
+    };
     
+    Ok((checked_data(data)?, diagnostics))
+}
+
+// Validate, migrate to the current version, and validate again; every path that
+// reads data from outside goes through here
+fn checked_data(data: ParcelData) -> anyhow::Result<ParcelData> {
     // Validate data structure
     validate_data(&data)?;
     
     // Migrate data to current version if needed
-    data = migrate_data(data)?;
+    let data = migrate_data(data)?;
     
     // Re-validate after migration
     validate_data(&data)?;
-    
+    Ok(data)
+}
+
+// Imports refuse data from the future outright instead of migrating it. Runs
+// before `checked_data`, whose range check would give a vaguer error.
+fn check_import_version(data: &ParcelData) -> Result<(), ParcelError> {
+    if data.version > CURRENT_VERSION {
+        return Err(ParcelError::UnsupportedVersion {
+            found: data.version,
+            supported: CURRENT_VERSION,
+        });
+    }
+    Ok(())
+}
+
+// Put the newest backup that parses back in place of a corrupt notes.json, moving
+// the corrupt file aside first. None if there is no such backup or it has
+// auto_recover off.
+fn recover_from_backup(app_data_dir: PathBuf) -> anyhow::Result<Option<ParcelData>> {
+    let path = data_file(app_data_dir.clone());
+    for (_, backup) in list_backups(backups_dir(app_data_dir))?.into_iter().rev() {
+        let Ok(s) = fs::read_to_string(&backup) else {
+            continue;
+        };
+        let Ok(data) = serde_json::from_str::<ParcelData>(strip_bom(&s).0) else {
+            continue;
+        };
+        if validate_data(&data).is_err() {
+            continue;
+        }
+        if !data.auto_recover {
+            return Ok(None);
+        }
+        fs::rename(&path, path.with_extension("json.corrupt"))?;
+        fs::write(&path, &s)?;
+        return Ok(Some(data));
+    }
+    Ok(None)
+}
+
+// Assemble data from the split layout. folders.json lists note ids in order;
+// note files it doesn't mention are appended so nothing is silently lost.
+fn read_split(app_data_dir: PathBuf) -> anyhow::Result<ParcelData> {
+    let s = fs::read_to_string(folders_file(app_data_dir.clone()))?;
+    let mut value: serde_json::Value = serde_json::from_str(&s)
+        .map_err(|e| anyhow::anyhow!("Failed to parse folders.json: {}. File may be corrupt.", e))?;
+    let order: Vec<String> = serde_json::from_value(value["notes"].take()).unwrap_or_default();
+    value["notes"] = serde_json::Value::Array(Vec::new());
+    let mut data: ParcelData = serde_json::from_value(value)
+        .map_err(|e| anyhow::anyhow!("Failed to parse folders.json: {}. File may be corrupt.", e))?;
+
+    let dir = split_notes_dir(app_data_dir);
+    let mut ids = order;
+    if dir.exists() {
+        let mut extra: Vec<String> = fs::read_dir(&dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|p| Some(p.file_stem()?.to_string_lossy().to_string()))
+            .filter(|id| !ids.contains(id))
+            .collect();
+        extra.sort();
+        ids.extend(extra);
+    }
+    for id in ids {
+        let path = dir.join(format!("{}.json", id));
+        if !path.exists() {
+            continue;
+        }
+        let s = fs::read_to_string(&path)?;
+        let note: Note = serde_json::from_str(&s)
+            .map_err(|e| anyhow::anyhow!("Failed to parse note {}: {}. File may be corrupt.", id, e))?;
+        data.notes.push(note);
+    }
     Ok(data)
 }
 
@@ -81,8 +747,7 @@ fn validate_data(data: &ParcelData) -> anyhow::Result<()> {
             return Err(anyhow::anyhow!("Note at index {} has empty ID", idx));
         }
         // Validate color
-        let valid_colors = ["paper", "yellow", "mint", "lavender", "salmon", "sky"];
-        if !valid_colors.contains(&note.color.as_str()) {
+        if !NOTE_COLORS.contains(&note.color.as_str()) {
             // Auto-fix invalid colors
             // This will be handled in migration, but we log it here
         }
@@ -102,31 +767,18 @@ fn validate_data(data: &ParcelData) -> anyhow::Result<()> {
 }
 
 fn migrate_data(mut data: ParcelData) -> anyhow::Result<ParcelData> {
-    const CURRENT_VERSION: u32 = 1;
-    
     // If already at current version, just fix any invalid data
     if data.version >= CURRENT_VERSION {
-        // Fix invalid colors
-        let valid_colors = ["paper", "yellow", "mint", "lavender", "salmon", "sky"];
-        for note in &mut data.notes {
-            if !valid_colors.contains(&note.color.as_str()) {
-                note.color = "paper".to_string();
-            }
-        }
+        repair_data(&mut data);
         return Ok(data);
     }
     
-    // Migration logic for future versions
-    // Example: if data.version == 0, migrate to version 1
-    // For now, version 1 is the initial version, so no migration needed
+    // Migration logic for older versions
+    // v1 -> v2: notes gained `tags`, which serde already defaults to empty, as
+    // it does `attachments`
     
-    // Fix invalid colors during migration
-    let valid_colors = ["paper", "yellow", "mint", "lavender", "salmon", "sky"];
-    for note in &mut data.notes {
-        if !valid_colors.contains(&note.color.as_str()) {
-            note.color = "paper".to_string();
-        }
-    }
+    // Fix invalid data during migration
+    repair_data(&mut data);
     
     // Update version to current
     data.version = CURRENT_VERSION;
@@ -134,62 +786,6297 @@ fn migrate_data(mut data: ParcelData) -> anyhow::Result<ParcelData> {
     Ok(data)
 }
 
-pub fn save(app_data_dir: PathBuf, data: &ParcelData) -> anyhow::Result<()> {
-    let path = data_file(app_data_dir);
+fn repair_data(data: &mut ParcelData) {
+    // Fix invalid colors
+    for note in &mut data.notes {
+        if !NOTE_COLORS.contains(&note.color.as_str()) {
+            note.color = "paper".to_string();
+        }
+    }
+
+    // Notes pinned before pinned_at existed keep their updated time as pin time
+    for note in &mut data.notes {
+        if !note.pinned {
+            note.pinned_at = None;
+        } else if note.pinned_at.is_none() {
+            note.pinned_at = Some(note.updated_at);
+        }
+    }
+
+    // Drop a default folder that has since been deleted
+    if let Some(folder_id) = &data.default_folder_id {
+        if !data.folders.iter().any(|f| &f.id == folder_id) {
+            data.default_folder_id = None;
+        }
+    }
+    let folders = &data.folders;
+    data.folder_default_tags.retain(|folder_id, _| folders.iter().any(|f| &f.id == folder_id));
+}
+
+// Normalizes, then bumps `save_seq` so the stored counter matches what is
+// written; the bump is rolled back if the write fails
+pub fn save(app_data_dir: PathBuf, data: &mut ParcelData) -> anyhow::Result<()> {
+    normalize_data(data);
+    data.save_seq += 1;
+    let result = write_data(app_data_dir, data);
+    if result.is_err() {
+        data.save_seq -= 1;
+    }
+    result
+}
+
+// First non-blank line of a body, trimmed
+fn first_line(body: &str) -> Option<&str> {
+    body.lines().map(str::trim).find(|l| !l.is_empty())
+}
+
+// Fix-ups applied to every save
+fn normalize_data(data: &mut ParcelData) {
+    // Whatever version the caller last saw, what gets written is the current
+    // format; `write_data` downgrades it if a save version is pinned
+    data.version = CURRENT_VERSION;
+    if data.auto_title_from_body {
+        for note in data.notes.iter_mut().filter(|n| n.title.trim().is_empty()) {
+            if let Some(line) = first_line(&note.body) {
+                let title: String = line.chars().take(AUTO_TITLE_MAX_CHARS).collect();
+                note.title = title.trim_end().to_string();
+            }
+        }
+    }
+    compact(data);
+}
+
+// Data files are pretty-printed unless the user asked for small files, and end
+// in a newline only if asked. Either way the order of notes and folders is
+// exactly as given. `settings` is the data being saved.
+fn stored_json<T: Serialize + ?Sized>(value: &T, settings: &ParcelData) -> serde_json::Result<String> {
+    let mut json = if settings.minify_json {
+        serde_json::to_string(value)?
+    } else {
+        serde_json::to_string_pretty(value)?
+    };
+    if settings.trailing_newline {
+        json.push('\n');
+    }
+    Ok(json)
+}
+
+fn write_data(app_data_dir: PathBuf, data: &ParcelData) -> anyhow::Result<()> {
+    // An older pinned version is written the way `export_json_at_version` would
+    let pinned = data.pinned_save_version.filter(|v| *v < CURRENT_VERSION);
+    if data.storage_layout == "split" {
+        return save_split(app_data_dir, data, pinned);
+    }
+    let path = data_file(app_data_dir.clone());
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)?;
     }
-    let s = serde_json::to_string_pretty(data)?;
+    let s = match pinned {
+        Some(version) => stored_json(&json_at_version(data, version)?, data)?,
+        None => stored_json(data, data)?,
+    };
     fs::write(path, s)?;
+
+    // Only drop the split files once notes.json is fully written
+    let folders = folders_file(app_data_dir.clone());
+    if folders.exists() {
+        fs::remove_file(folders)?;
+    }
+    let notes_dir = split_notes_dir(app_data_dir);
+    if notes_dir.exists() {
+        fs::remove_dir_all(notes_dir)?;
+    }
     Ok(())
 }
 
-// Export data to JSON string
-pub fn export_json(data: &ParcelData) -> anyhow::Result<String> {
-    Ok(serde_json::to_string_pretty(data)?)
-}
+// Write the split layout, touching only note files whose content changed
+fn save_split(app_data_dir: PathBuf, data: &ParcelData, pinned: Option<u32>) -> anyhow::Result<()> {
+    let dir = split_notes_dir(app_data_dir.clone());
+    fs::create_dir_all(&dir)?;
+    let mut value = match pinned {
+        Some(version) => json_at_version(data, version)?,
+        None => serde_json::to_value(data)?,
+    };
 
-// Export data to Markdown format
-pub fn export_markdown(data: &ParcelData) -> anyhow::Result<String> {
-    use std::fmt::Write;
-    
-    let mut output = String::new();
-    writeln!(output, "# Parcel Notes Export\n")?;
-    writeln!(output, "*Total notes: {}*", data.notes.len())?;
-    writeln!(output, "*Total folders: {}*\n", data.folders.len())?;
-    
-    // Group notes by folder
-    let mut notes_by_folder: std::collections::HashMap<Option<String>, Vec<&Note>> = std::collections::HashMap::new();
-    for note in &data.notes {
-        notes_by_folder.entry(note.folder_id.clone()).or_insert_with(Vec::new).push(note);
+    let mut keep = Vec::with_capacity(data.notes.len());
+    for (i, note) in data.notes.iter().enumerate() {
+        if note.id.is_empty()
+            || !note
+                .id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(anyhow::anyhow!("Note id {:?} can't be used as a file name", note.id));
+        }
+        let file_name = format!("{}.json", note.id);
+        let path = dir.join(&file_name);
+        let s = match pinned {
+            Some(_) => stored_json(&value["notes"][i], data)?,
+            None => stored_json(note, data)?,
+        };
+        if fs::read_to_string(&path).ok().as_deref() != Some(s.as_str()) {
+            fs::write(&path, s)?;
+        }
+        keep.push(std::ffi::OsString::from(file_name));
     }
-    
-    // Export notes in folders
-    for folder in &data.folders {
-        writeln!(output, "## Folder: {}\n", folder.name)?;
-        
-        if let Some(notes) = notes_by_folder.get(&Some(folder.id.clone())) {
-            for note in notes {
-                writeln!(output, "### {}\n", if note.title.is_empty() { "Untitled" } else { &note.title })?;
-                if !note.body.is_empty() {
-                    writeln!(output, "{}\n", note.body)?;
-                }
-                writeln!(output, "*Color: {} | Pinned: {}*\n", note.color, note.pinned)?;
-            }
+
+    // Remove files for notes that no longer exist
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        if !keep.contains(&entry.file_name()) {
+            fs::remove_file(entry.path())?;
         }
     }
-    
-    // Export notes without folders
-    if let Some(notes) = notes_by_folder.get(&None) {
-        writeln!(output, "## Notes (No Folder)\n")?;
-        for note in notes {
-            writeln!(output, "### {}\n", if note.title.is_empty() { "Untitled" } else { &note.title })?;
-            if !note.body.is_empty() {
-                writeln!(output, "{}\n", note.body)?;
+
+    value["notes"] = data.notes.iter().map(|n| n.id.clone()).collect();
+    fs::write(folders_file(app_data_dir.clone()), stored_json(&value, data)?)?;
+
+    // Only drop notes.json once the split files are fully written
+    let single = data_file(app_data_dir);
+    if single.exists() {
+        fs::remove_file(single)?;
+    }
+    Ok(())
+}
+
+// Switch the on-disk layout. Backs up first and checks the converted data
+// reads back identically, restoring the previous layout if it doesn't.
+pub fn convert_storage_layout(app_data_dir: PathBuf, to: &str, now: u64) -> anyhow::Result<()> {
+    if !STORAGE_LAYOUTS.contains(&to) {
+        return Err(anyhow::anyhow!(
+            "Invalid storage layout: {}. Expected one of: {}",
+            to,
+            STORAGE_LAYOUTS.join(", ")
+        ));
+    }
+    let mut original = load(app_data_dir.clone())?;
+    if original.storage_layout == to {
+        return Ok(());
+    }
+    backup(app_data_dir.clone(), now)?;
+
+    let mut converted = original.clone();
+    converted.storage_layout = to.to_string();
+    save(app_data_dir.clone(), &mut converted)?;
+
+    if load(app_data_dir.clone())? != converted {
+        save(app_data_dir, &mut original)?;
+        return Err(anyhow::anyhow!(
+            "Converted data didn't read back identically; kept the {} layout",
+            original.storage_layout
+        ));
+    }
+    Ok(())
+}
+
+// Record a note edit in the WAL so it survives a crash before the next full save
+pub fn append_wal(app_data_dir: PathBuf, note: &Note) -> anyhow::Result<()> {
+    use std::io::Write;
+
+    let path = wal_file(app_data_dir);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(note)?)?;
+    Ok(())
+}
+
+// Edits in the WAL, oldest first. Reading stops at the first line that doesn't
+// parse, which is what a write torn by a crash looks like.
+fn read_wal(app_data_dir: PathBuf) -> anyhow::Result<Vec<Note>> {
+    let path = wal_file(app_data_dir);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let s = fs::read_to_string(path)?;
+    Ok(s.lines()
+        .map_while(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+// Ids of notes with edits in the WAL that aren't in a full save yet
+pub fn pending_changes(app_data_dir: PathBuf) -> anyhow::Result<Vec<String>> {
+    let mut ids: Vec<String> = Vec::new();
+    for note in read_wal(app_data_dir)? {
+        if !ids.contains(&note.id) {
+            ids.push(note.id);
+        }
+    }
+    Ok(ids)
+}
+
+// Empty the WAL once its edits are part of a full save
+pub fn clear_wal(app_data_dir: PathBuf) -> anyhow::Result<()> {
+    let path = wal_file(app_data_dir);
+    if path.exists() {
+        fs::write(path, "")?;
+    }
+    Ok(())
+}
+
+// WAL edits that aren't in the data file yet. A WAL older than the data file
+// was already checkpointed by a save that crashed before truncating it.
+fn unsaved_wal_edits(app_data_dir: PathBuf) -> anyhow::Result<Vec<Note>> {
+    let wal = fs::metadata(wal_file(app_data_dir.clone()));
+    let Ok(wal) = wal else {
+        return Ok(Vec::new());
+    };
+    if wal.len() == 0 {
+        return Ok(Vec::new());
+    }
+
+    let single = data_file(app_data_dir.clone());
+    let saved_at = if single.exists() {
+        fs::metadata(single)?.modified().ok()
+    } else {
+        fs::metadata(folders_file(app_data_dir.clone()))
+            .and_then(|m| m.modified())
+            .ok()
+    };
+    let wal_is_newer = match (saved_at, wal.modified()) {
+        (Some(saved_at), Ok(logged_at)) => logged_at >= saved_at,
+        _ => true,
+    };
+    if !wal_is_newer {
+        return Ok(Vec::new());
+    }
+    read_wal(app_data_dir)
+}
+
+fn apply_wal_edits(data: &mut ParcelData, edits: Vec<Note>) -> usize {
+    let replayed = edits.len();
+    let max_revisions = data.max_revisions;
+    // Logged edits are often keystrokes apart, so only the saved version of a
+    // note becomes a revision, not each logged one
+    let mut revised = HashSet::new();
+    for mut note in edits {
+        match data.notes.iter_mut().find(|n| n.id == note.id) {
+            Some(existing) => {
+                note.revisions = std::mem::take(&mut existing.revisions);
+                if revised.insert(note.id.clone()) {
+                    push_revision(&mut note, existing, max_revisions);
+                }
+                *existing = note;
+            }
+            None => data.notes.insert(0, note),
+        }
+    }
+    replayed
+}
+
+// Load with unsaved WAL edits applied in memory. Nothing is written, so the
+// edits stay pending until a save or `replay_wal`.
+pub fn load_with_wal(app_data_dir: PathBuf) -> anyhow::Result<(ParcelData, LoadDiagnostics)> {
+    let (mut data, diagnostics) = load_with_diagnostics(app_data_dir.clone())?;
+    apply_wal_edits(&mut data, unsaved_wal_edits(app_data_dir)?);
+    Ok((data, diagnostics))
+}
+
+// Apply WAL edits newer than the data file, then checkpoint (full save and
+// truncate). Returns how many edits were replayed.
+pub fn replay_wal(app_data_dir: PathBuf) -> anyhow::Result<usize> {
+    let edits = unsaved_wal_edits(app_data_dir.clone())?;
+    let mut replayed = 0;
+    if !edits.is_empty() {
+        let mut data = load(app_data_dir.clone())?;
+        replayed = apply_wal_edits(&mut data, edits);
+        save(app_data_dir.clone(), &mut data)?;
+    }
+    clear_wal(app_data_dir)?;
+    Ok(replayed)
+}
+
+// Copy the current data into the backups directory, keeping the newest
+// MAX_BACKUPS copies. Returns None when there is nothing to back up yet.
+pub fn backup(app_data_dir: PathBuf, now: u64) -> anyhow::Result<Option<PathBuf>> {
+    let source = data_file(app_data_dir.clone());
+    // The split layout has no single file to copy, so snapshot it as one
+    let snapshot = if source.exists() {
+        fs::read(&source)?
+    } else if folders_file(app_data_dir.clone()).exists() {
+        serde_json::to_vec_pretty(&read_split(app_data_dir.clone())?)?
+    } else {
+        return Ok(None);
+    };
+    let dir = backups_dir(app_data_dir);
+    fs::create_dir_all(&dir)?;
+    let target = dir.join(format!("notes-{}.json", now));
+    fs::write(&target, snapshot)?;
+
+    let mut backups = list_backups(dir)?;
+    while backups.len() > MAX_BACKUPS {
+        fs::remove_file(backups.remove(0).1)?;
+    }
+    Ok(Some(target))
+}
+
+// Back up unless the newest backup is younger than `min_interval_ms`. Meant for
+// routine saves; destructive operations should call `backup` directly.
+pub fn backup_if_due(
+    app_data_dir: PathBuf,
+    now: u64,
+    min_interval_ms: u64,
+) -> anyhow::Result<Option<PathBuf>> {
+    let latest = list_backups(backups_dir(app_data_dir.clone()))?
+        .last()
+        .map(|(taken_at, _)| *taken_at);
+    if latest.is_some_and(|taken_at| now.saturating_sub(taken_at) < min_interval_ms) {
+        return Ok(None);
+    }
+    backup(app_data_dir, now)
+}
+
+// Timestamp of a backup file named notes-<ms>.json
+fn backup_timestamp(path: &Path) -> Option<u64> {
+    let name = path.file_name()?.to_str()?;
+    let digits = name.strip_prefix("notes-")?.strip_suffix(".json")?;
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+// Backups in `dir`, oldest first
+fn list_backups(dir: PathBuf) -> anyhow::Result<Vec<(u64, PathBuf)>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut backups: Vec<(u64, PathBuf)> = fs::read_dir(&dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter_map(|p| Some((backup_timestamp(&p)?, p)))
+        .collect();
+    backups.sort();
+    Ok(backups)
+}
+
+// Where the data directory lives, for warning about flaky storage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VolumeInfo {
+    // "local", "removable", "network", or "unknown" when detection isn't possible
+    pub kind: String,
+    pub writable: bool,
+}
+
+// File systems that live on another machine
+#[cfg(target_os = "linux")]
+const NETWORK_FILESYSTEMS: [&str; 11] = [
+    "nfs", "nfs4", "cifs", "smb3", "smbfs", "fuse.sshfs", "9p", "afs", "ceph", "glusterfs", "davfs",
+];
+
+// Best-effort: on Linux the mount table and sysfs, elsewhere only path shape
+pub fn data_volume_info(app_data_dir: PathBuf) -> VolumeInfo {
+    let parcel_dir = app_data_dir.join("parcel");
+    let dir = if parcel_dir.exists() { parcel_dir } else { app_data_dir };
+    let probe = dir.join(".parcel-write-test");
+    let writable = fs::write(&probe, b"").is_ok();
+    if writable {
+        let _ = fs::remove_file(&probe);
+    }
+    VolumeInfo {
+        kind: volume_kind(&dir).unwrap_or("unknown").to_string(),
+        writable,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn volume_kind(dir: &Path) -> Option<&'static str> {
+    // Octal escapes such as \040 for spaces in mount points
+    fn unescape(field: &str) -> String {
+        let mut out = String::new();
+        let mut rest = field;
+        while let Some(i) = rest.find('\\') {
+            out.push_str(&rest[..i]);
+            match u8::from_str_radix(rest.get(i + 1..i + 4).unwrap_or(""), 8) {
+                Ok(byte) => {
+                    out.push(byte as char);
+                    rest = &rest[i + 4..];
+                }
+                Err(_) => {
+                    out.push('\\');
+                    rest = &rest[i + 1..];
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+
+    let dir = fs::canonicalize(dir).ok()?;
+    let mounts = fs::read_to_string("/proc/mounts").ok()?;
+    let (device, fs_type) = mounts
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (device, mount_point, fs_type) = (fields.first()?, fields.get(1)?, fields.get(2)?);
+            let mount_point = PathBuf::from(unescape(mount_point));
+            let depth = mount_point.components().count();
+            dir.starts_with(&mount_point)
+                .then(|| (depth, device.to_string(), fs_type.to_string()))
+        })
+        .max_by_key(|(depth, _, _)| *depth)
+        .map(|(_, device, fs_type)| (device, fs_type))?;
+
+    if NETWORK_FILESYSTEMS.contains(&fs_type.as_str()) {
+        return Some("network");
+    }
+    // Partitions have no `removable` flag of their own; their disk does
+    if let Some(name) = device.strip_prefix("/dev/") {
+        if let Ok(block) = fs::canonicalize(Path::new("/sys/class/block").join(name)) {
+            for candidate in [Some(block.as_path()), block.parent()].into_iter().flatten() {
+                if let Ok(flag) = fs::read_to_string(candidate.join("removable")) {
+                    return Some(if flag.trim() == "1" { "removable" } else { "local" });
+                }
+            }
+        }
+    }
+    Some("local")
+}
+
+#[cfg(target_os = "macos")]
+fn volume_kind(dir: &Path) -> Option<&'static str> {
+    // Everything but the boot volume is mounted under /Volumes
+    Some(if dir.starts_with("/Volumes") { "removable" } else { "local" })
+}
+
+#[cfg(windows)]
+fn volume_kind(dir: &Path) -> Option<&'static str> {
+    // UNC paths (\\server\share) are network shares; drive letters need OS calls to classify
+    dir.to_str()?.starts_with(r"\\").then_some("network")
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn volume_kind(_dir: &Path) -> Option<&'static str> {
+    None
+}
+
+// Remove files in the backups directory that aren't notes-<ms>.json backups.
+// Subdirectories are left alone. Returns how many files were removed.
+pub fn clean_backups(app_data_dir: PathBuf) -> anyhow::Result<usize> {
+    let dir = backups_dir(app_data_dir);
+    if !dir.exists() {
+        return Ok(0);
+    }
+    let mut removed = 0;
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_file() && backup_timestamp(&path).is_none() {
+            fs::remove_file(path)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+fn display_title<'a>(note: &'a Note, placeholder: &'a str) -> &'a str {
+    if note.title.is_empty() {
+        placeholder
+    } else {
+        &note.title
+    }
+}
+
+// Export data to JSON string
+pub fn export_json(data: &ParcelData) -> anyhow::Result<String> {
+    Ok(serde_json::to_string_pretty(data)?)
+}
+
+// Same content as `export_json`, as YAML for hand-editing
+pub fn export_yaml(data: &ParcelData) -> anyhow::Result<String> {
+    Ok(serde_yaml::to_string(data)?)
+}
+
+pub fn import_yaml(yaml: &str) -> anyhow::Result<ParcelData> {
+    let data: ParcelData = serde_yaml::from_str(strip_bom(yaml).0)
+        .map_err(|e| anyhow::anyhow!("Failed to parse YAML: {}", e))?;
+    check_import_version(&data)?;
+    checked_data(data)
+}
+
+// Write each folder to `<folder-slug>.json` under `root`, and unfiled notes to
+// `_folderless.json`. With `by_date`, folders are ignored and each note goes to
+// `YYYY/MM/<title-slug>.json` by its UTC creation month instead. Every file is a
+// standalone data file that loads on its own. Returns how many files were written.
+pub fn export_folders_json_tree(data: &ParcelData, root: &Path, by_date: bool) -> anyhow::Result<usize> {
+    fs::create_dir_all(root)?;
+    if by_date {
+        return export_date_json_tree(data, root);
+    }
+    let mut written: Vec<String> = Vec::new();
+    let mut write = |file_name: String, part: ParcelData| -> anyhow::Result<()> {
+        fs::write(root.join(&file_name), serde_json::to_string_pretty(&part)?)?;
+        written.push(file_name);
+        Ok(())
+    };
+
+    let mut names: Vec<String> = Vec::new();
+    for folder in &data.folders {
+        let stem = match slugify(&folder.name) {
+            stem if stem.is_empty() => folder.id.clone(),
+            stem => stem,
+        };
+        let file_name = unique_file_name(&stem, "json", |f| names.iter().any(|n| n == f));
+        names.push(file_name.clone());
+        let notes = data
+            .notes
+            .iter()
+            .filter(|n| n.folder_id.as_deref() == Some(folder.id.as_str()))
+            .cloned()
+            .collect();
+        write(
+            file_name,
+            ParcelData {
+                notes,
+                folders: vec![folder.clone()],
+                ..ParcelData::empty()
+            },
+        )?;
+    }
+
+    let unfiled: Vec<Note> = data.notes.iter().filter(|n| n.folder_id.is_none()).cloned().collect();
+    if !unfiled.is_empty() {
+        write(
+            "_folderless.json".to_string(),
+            ParcelData {
+                notes: unfiled,
+                ..ParcelData::empty()
+            },
+        )?;
+    }
+    Ok(written.len())
+}
+
+fn export_date_json_tree(data: &ParcelData, root: &Path) -> anyhow::Result<usize> {
+    let mut written: Vec<String> = Vec::new();
+    for note in &data.notes {
+        let month = utc_day(note.created_at)
+            .map(|d| d.format("%Y/%m").to_string())
+            .ok_or_else(|| anyhow::anyhow!("Note {} has an invalid creation time", note.id))?;
+        let stem = match slugify(&note.title) {
+            stem if stem.is_empty() => note.id.clone(),
+            stem => stem,
+        };
+        let file_name = unique_file_name(&stem, "json", |f| {
+            written.iter().any(|w| *w == format!("{}/{}", month, f))
+        });
+        let dir = root.join(&month);
+        fs::create_dir_all(&dir)?;
+        let part = ParcelData {
+            notes: vec![Note {
+                folder_id: None,
+                ..note.clone()
+            }],
+            ..ParcelData::empty()
+        };
+        fs::write(dir.join(&file_name), serde_json::to_string_pretty(&part)?)?;
+        written.push(format!("{}/{}", month, file_name));
+    }
+    Ok(written.len())
+}
+
+// Export data as JSON readable by an older app version, dropping fields that
+// didn't exist yet at `version`
+pub fn export_json_at_version(data: &ParcelData, version: u32) -> anyhow::Result<String> {
+    Ok(serde_json::to_string_pretty(&json_at_version(data, version)?)?)
+}
+
+fn check_format_version(version: u32) -> anyhow::Result<()> {
+    if version == 0 || version > CURRENT_VERSION {
+        return Err(anyhow::anyhow!(
+            "Unsupported format version {}. Expected 1-{}.",
+            version,
+            CURRENT_VERSION
+        ));
+    }
+    Ok(())
+}
+
+fn json_at_version(data: &ParcelData, version: u32) -> anyhow::Result<serde_json::Value> {
+    check_format_version(version)?;
+    let mut value = serde_json::to_value(data)?;
+    for (_, note_fields, data_fields) in SCHEMA_ADDITIONS.iter().filter(|(v, _, _)| *v > version) {
+        if let Some(notes) = value["notes"].as_array_mut() {
+            for note in notes.iter_mut().filter_map(|n| n.as_object_mut()) {
+                for field in *note_fields {
+                    note.remove(*field);
+                }
+            }
+        }
+        if let Some(root) = value.as_object_mut() {
+            for field in *data_fields {
+                root.remove(*field);
+            }
+        }
+    }
+    value["version"] = version.into();
+    Ok(value)
+}
+
+// Note and folder fields as they existed at `version`
+pub fn schema_fields(version: u32) -> anyhow::Result<Vec<FieldInfo>> {
+    check_format_version(version)?;
+    let added_later = |entity: &str, name: &str| {
+        SCHEMA_ADDITIONS.iter().any(|(v, note_fields, folder_fields)| {
+            let fields = if entity == "note" { note_fields } else { folder_fields };
+            *v > version && fields.contains(&name)
+        })
+    };
+    let mut fields = Vec::new();
+    for (entity, table) in [("note", NOTE_FIELDS), ("folder", FOLDER_FIELDS)] {
+        for (name, json_type, required) in table {
+            if added_later(entity, name) {
+                continue;
             }
-            writeln!(output, "*Color: {} | Pinned: {}*\n", note.color, note.pinned)?;
+            fields.push(FieldInfo {
+                entity: entity.to_string(),
+                name: name.to_string(),
+                json_type: json_type.to_string(),
+                required: *required,
+            });
         }
     }
+    Ok(fields)
+}
+
+// Make every save write `version` (None to always write the current one)
+pub fn set_pinned_save_version(data: &mut ParcelData, version: Option<u32>) -> anyhow::Result<()> {
+    if let Some(version) = version {
+        check_format_version(version)?;
+    }
+    data.pinned_save_version = version;
+    Ok(())
+}
+
+// Export data to Markdown format
+pub fn export_markdown(data: &ParcelData, options: &ExportOptions) -> anyhow::Result<String> {
+    use std::fmt::Write;
+    
+    let data = &*export_data(data, options);
+    let mut output = String::new();
+    if let Some(header) = &options.header {
+        writeln!(output, "{}\n", header)?;
+    }
+    writeln!(output, "# Parcel Notes Export\n")?;
+    writeln!(output, "*Total notes: {}*", data.notes.len())?;
+    writeln!(output, "*Total folders: {}*\n", data.folders.len())?;
+    
+    write_note_groups(&mut output, data, options, true)?;
+
+    if let Some(footer) = &options.footer {
+        writeln!(output, "{}", footer)?;
+    }
     
     Ok(output)
 }
+
+// Notes under a heading per folder, then unfiled notes
+fn write_note_groups(
+    output: &mut String,
+    data: &ParcelData,
+    options: &ExportOptions,
+    include_empty_folders: bool,
+) -> std::fmt::Result {
+    use std::fmt::Write;
+
+    // Group notes by folder
+    let mut notes_by_folder: HashMap<Option<String>, Vec<&Note>> = HashMap::new();
+    for note in &data.notes {
+        notes_by_folder.entry(note.folder_id.clone()).or_default().push(note);
+    }
+    
+    // Export notes in folders
+    for folder in &data.folders {
+        let notes = notes_by_folder.get(&Some(folder.id.clone()));
+        if notes.is_none() && !include_empty_folders {
+            continue;
+        }
+        writeln!(output, "## Folder: {}\n", folder.name)?;
+        
+        write_markdown_notes(output, notes.map_or(&[], |n| n.as_slice()), data, options)?;
+    }
+    
+    // Export notes without folders
+    if let Some(notes) = notes_by_folder.get(&None) {
+        writeln!(output, "## Notes (No Folder)\n")?;
+        write_markdown_notes(output, notes, data, options)?;
+    }
+    Ok(())
+}
+
+fn write_markdown_notes(
+    output: &mut String,
+    notes: &[&Note],
+    data: &ParcelData,
+    options: &ExportOptions,
+) -> std::fmt::Result {
+    use std::fmt::Write;
+
+    for (i, note) in notes.iter().enumerate() {
+        if let Some(separator) = options.separator.as_ref().filter(|_| i > 0) {
+            writeln!(output, "{}\n", separator)?;
+        }
+        write_markdown_note(output, note, &data.untitled_placeholder, options)?;
+    }
+    Ok(())
+}
+
+fn write_markdown_note(
+    output: &mut String,
+    note: &Note,
+    placeholder: &str,
+    options: &ExportOptions,
+) -> std::fmt::Result {
+    use std::fmt::Write;
+
+    writeln!(output, "### {}", display_title(note, placeholder))?;
+    if options.include_ids {
+        writeln!(output, "<!-- id: {} -->", note.id)?;
+    }
+    writeln!(output)?;
+    if !note.body.is_empty() {
+        let body = export_body(note, options);
+        if options.include_ids {
+            writeln!(output, "{}\n", escape_markdown_body(&body))?;
+        } else {
+            writeln!(output, "{}\n", body)?;
+        }
+    }
+    if options.word_count {
+        writeln!(
+            output,
+            "*Color: {} | Pinned: {} | Words: {}*\n",
+            note.color,
+            note.pinned,
+            word_count(&note.body)
+        )
+    } else {
+        writeln!(output, "*Color: {} | Pinned: {}*\n", note.color, note.pinned)
+    }
+}
+
+// Body lines `import_markdown` would read as structure (headings, id comments,
+// metadata lines) get a leading backslash, as do lines already starting with
+// one, so `unescape_markdown_line` can take exactly one back off. Only exports
+// with `include_ids`, which are meant for re-import, are escaped.
+fn escape_markdown_body(body: &str) -> String {
+    body.split('\n')
+        .map(|line| {
+            let trimmed = line.trim();
+            if line.starts_with(['#', '\\'])
+                || trimmed.starts_with("<!--")
+                || parse_markdown_meta(trimmed).is_some()
+            {
+                format!("\\{}", line)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn unescape_markdown_line(line: &str) -> &str {
+    line.strip_prefix('\\').unwrap_or(line)
+}
+
+// Live notes that haven't been updated since `now - older_than_ms`, oldest first
+pub fn stale_notes(data: &ParcelData, now: u64, older_than_ms: u64) -> Vec<Note> {
+    let cutoff = now.saturating_sub(older_than_ms);
+    let mut stale: Vec<Note> = data
+        .notes
+        .iter()
+        .filter(|n| !n.archived && n.updated_at < cutoff)
+        .cloned()
+        .collect();
+    stale.sort_by_key(|n| n.updated_at);
+    stale
+}
+
+// Move a note to `folder_b` if it's in `folder_a`, otherwise to `folder_a`.
+// Returns the folder the note ended up in.
+pub fn toggle_note_folder(
+    data: &mut ParcelData,
+    note_id: &str,
+    folder_a: &str,
+    folder_b: &str,
+    now: u64,
+) -> anyhow::Result<String> {
+    for folder_id in [folder_a, folder_b] {
+        if !data.folders.iter().any(|f| f.id == folder_id) {
+            return Err(anyhow::anyhow!("Folder {} not found", folder_id));
+        }
+    }
+    let touch_on_metadata = data.touch_on_metadata;
+    let note = data
+        .notes
+        .iter_mut()
+        .find(|n| n.id == note_id)
+        .ok_or_else(|| anyhow::anyhow!("Note {} not found", note_id))?;
+    let target = if note.folder_id.as_deref() == Some(folder_a) {
+        folder_b
+    } else {
+        folder_a
+    };
+    note.folder_id = Some(target.to_string());
+    touch_metadata(note, touch_on_metadata, now);
+    Ok(target.to_string())
+}
+
+fn counter_id(n: u64) -> String {
+    format!("note-{:04}", n)
+}
+
+// Generate a note id according to the configured id strategy
+pub fn new_id(data: &mut ParcelData) -> String {
+    if data.id_strategy != "counter" {
+        return uuid::Uuid::new_v4().to_string();
+    }
+    loop {
+        let id = counter_id(data.next_counter);
+        data.next_counter += 1;
+        if !data.notes.iter().any(|n| n.id == id) {
+            return id;
+        }
+    }
+}
+
+pub fn set_id_strategy(data: &mut ParcelData, strategy: &str) -> anyhow::Result<()> {
+    if !ID_STRATEGIES.contains(&strategy) {
+        return Err(anyhow::anyhow!(
+            "Invalid id strategy: {}. Expected one of: {}",
+            strategy,
+            ID_STRATEGIES.join(", ")
+        ));
+    }
+    if strategy == "counter" {
+        // Start past any counter ids already in use so switching back and forth can't collide
+        let highest = data
+            .notes
+            .iter()
+            .filter_map(|n| n.id.strip_prefix("note-")?.parse::<u64>().ok())
+            .max()
+            .unwrap_or(0);
+        data.next_counter = data.next_counter.max(highest + 1);
+    }
+    data.id_strategy = strategy.to_string();
+    Ok(())
+}
+
+pub fn set_default_folder(data: &mut ParcelData, folder_id: Option<String>) -> anyhow::Result<()> {
+    if let Some(folder_id) = &folder_id {
+        if !data.folders.iter().any(|f| &f.id == folder_id) {
+            return Err(anyhow::anyhow!("Folder {} not found", folder_id));
+        }
+    }
+    data.default_folder_id = folder_id;
+    Ok(())
+}
+
+pub fn set_untitled_placeholder(data: &mut ParcelData, text: &str) -> anyhow::Result<()> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(anyhow::anyhow!("Untitled placeholder can't be empty"));
+    }
+    data.untitled_placeholder = text.to_string();
+    Ok(())
+}
+
+// Create an empty note at the top of the list, in the default folder if none is given
+pub fn create_note(
+    data: &mut ParcelData,
+    folder_id: Option<String>,
+    color: &str,
+    now: u64,
+) -> anyhow::Result<Note> {
+    let folder_id = new_note_folder(data, folder_id, color)?;
+    let note = Note {
+        id: new_id(data),
+        title: String::new(),
+        body: String::new(),
+        tags: default_tags(data, &folder_id),
+        folder_id,
+        pinned: false,
+        color: color.to_string(),
+        created_at: now,
+        updated_at: now,
+        ..Default::default()
+    };
+    data.notes.insert(0, note.clone());
+    Ok(note)
+}
+
+// Checks the color and resolves the folder (falling back to the default) for new notes
+fn new_note_folder(
+    data: &ParcelData,
+    folder_id: Option<String>,
+    color: &str,
+) -> anyhow::Result<Option<String>> {
+    if !NOTE_COLORS.contains(&color) {
+        return Err(anyhow::anyhow!("Invalid color: {}", color));
+    }
+    let folder_id = folder_id.or_else(|| data.default_folder_id.clone());
+    if let Some(folder_id) = &folder_id {
+        if !data.folders.iter().any(|f| &f.id == folder_id) {
+            return Err(anyhow::anyhow!("Folder {} not found", folder_id));
+        }
+    }
+    Ok(folder_id)
+}
+
+fn default_tags(data: &ParcelData, folder_id: &Option<String>) -> Vec<String> {
+    folder_id
+        .as_ref()
+        .and_then(|id| data.folder_default_tags.get(id))
+        .cloned()
+        .unwrap_or_default()
+}
+
+// Tags for new notes in `folder_id`; an empty list clears them
+pub fn set_folder_default_tags(
+    data: &mut ParcelData,
+    folder_id: &str,
+    tags: &[String],
+) -> anyhow::Result<()> {
+    if !data.folders.iter().any(|f| f.id == folder_id) {
+        return Err(anyhow::anyhow!("Folder {} not found", folder_id));
+    }
+    let tags = normalized_tags(tags);
+    if tags.is_empty() {
+        data.folder_default_tags.remove(folder_id);
+    } else {
+        data.folder_default_tags.insert(folder_id.to_string(), tags);
+    }
+    Ok(())
+}
+
+// One empty note per non-blank title, kept in the given order at the top of the list
+pub fn create_notes_batch(
+    data: &mut ParcelData,
+    titles: &[String],
+    folder_id: Option<String>,
+    color: &str,
+    now: u64,
+    mut id_gen: impl FnMut(&mut ParcelData) -> String,
+) -> anyhow::Result<Vec<Note>> {
+    let folder_id = new_note_folder(data, folder_id, color)?;
+    let tags = default_tags(data, &folder_id);
+    let mut created = Vec::new();
+    for title in titles.iter().map(|t| t.trim()).filter(|t| !t.is_empty()) {
+        created.push(Note {
+            id: id_gen(data),
+            title: title.to_string(),
+            body: String::new(),
+            tags: tags.clone(),
+            folder_id: folder_id.clone(),
+            pinned: false,
+            color: color.to_string(),
+            created_at: now,
+            updated_at: now,
+            ..Default::default()
+        });
+    }
+    data.notes.splice(0..0, created.iter().cloned());
+    Ok(created)
+}
+
+// UTC calendar day of an epoch-ms timestamp
+fn utc_day(ms: u64) -> Option<NaiveDate> {
+    DateTime::from_timestamp_millis(i64::try_from(ms).ok()?).map(|dt| dt.date_naive())
+}
+
+// Notes created per day (YYYY-MM-DD, UTC) for every day of `year`, including empty days
+pub fn activity_heatmap(data: &ParcelData, year: i32) -> Vec<(String, usize)> {
+    let mut counts: HashMap<NaiveDate, usize> = HashMap::new();
+    for note in &data.notes {
+        if let Some(day) = utc_day(note.created_at).filter(|d| d.year() == year) {
+            *counts.entry(day).or_default() += 1;
+        }
+    }
+    let Some(first) = NaiveDate::from_ymd_opt(year, 1, 1) else {
+        return Vec::new();
+    };
+    first
+        .iter_days()
+        .take_while(|d| d.year() == year)
+        .map(|d| (d.format("%Y-%m-%d").to_string(), counts.get(&d).copied().unwrap_or(0)))
+        .collect()
+}
+
+pub fn edit_age_summary(data: &ParcelData, now: u64) -> EditAgeSummary {
+    let mut summary = EditAgeSummary::default();
+    let Some(today) = utc_day(now) else {
+        summary.older = data.notes.len();
+        return summary;
+    };
+    for note in &data.notes {
+        let bucket = match utc_day(note.updated_at) {
+            // Edits stamped in the future count as today
+            Some(day) if day >= today => &mut summary.today,
+            Some(day) if day.iso_week() == today.iso_week() => &mut summary.this_week,
+            Some(day) if (day.year(), day.month()) == (today.year(), today.month()) => {
+                &mut summary.this_month
+            }
+            Some(day) if day.year() == today.year() => &mut summary.this_year,
+            _ => &mut summary.older,
+        };
+        *bucket += 1;
+    }
+    summary
+}
+
+// (YYYY-MM-DD, total words) for each UTC day a note was created, oldest first,
+// where the total covers every note created up to and including that day
+pub fn word_count_trend(data: &ParcelData) -> Vec<(String, usize)> {
+    let mut by_day: std::collections::BTreeMap<NaiveDate, usize> = Default::default();
+    for note in &data.notes {
+        if let Some(day) = utc_day(note.created_at) {
+            *by_day.entry(day).or_default() += word_count(&note.body);
+        }
+    }
+    let mut total = 0;
+    by_day
+        .into_iter()
+        .map(|(day, words)| {
+            total += words;
+            (day.format("%Y-%m-%d").to_string(), total)
+        })
+        .collect()
+}
+
+// Earliest and latest `created_at` over all notes, None without notes
+pub fn creation_span(data: &ParcelData) -> Option<(u64, u64)> {
+    let first = data.notes.iter().map(|n| n.created_at).min()?;
+    let last = data.notes.iter().map(|n| n.created_at).max()?;
+    Some((first, last))
+}
+
+pub fn edit_streak(data: &ParcelData, now: u64) -> StreakInfo {
+    let mut days: Vec<NaiveDate> = data.notes.iter().filter_map(|n| utc_day(n.updated_at)).collect();
+    days.sort();
+    days.dedup();
+
+    let mut longest = 0;
+    let mut run = 0;
+    let mut previous: Option<NaiveDate> = None;
+    for &day in &days {
+        run = match previous {
+            Some(p) if p.succ_opt() == Some(day) => run + 1,
+            _ => 1,
+        };
+        longest = longest.max(run);
+        previous = Some(day);
+    }
+
+    let alive = match (previous, utc_day(now)) {
+        (Some(last), Some(today)) => last == today || last.succ_opt() == Some(today),
+        _ => false,
+    };
+    StreakInfo {
+        current_streak: if alive { run } else { 0 },
+        longest_streak: longest,
+        last_edit_day: previous.map(|d| d.format("%Y-%m-%d").to_string()),
+    }
+}
+
+pub fn word_count(text: &str) -> usize {
+    text.split_whitespace().count()
+}
+
+// Per-folder totals, followed by an entry for notes without a folder
+pub fn folder_stats(data: &ParcelData) -> Vec<FolderStat> {
+    let stat_for = |folder_id: Option<&str>, name: &str| {
+        let mut stat = FolderStat {
+            folder_id: folder_id.map(str::to_string),
+            name: name.to_string(),
+            note_count: 0,
+            word_count: 0,
+            pinned_count: 0,
+            last_updated: None,
+        };
+        for note in data.notes.iter().filter(|n| n.folder_id.as_deref() == folder_id) {
+            stat.note_count += 1;
+            stat.word_count += word_count(&note.body);
+            if note.pinned {
+                stat.pinned_count += 1;
+            }
+            stat.last_updated = stat.last_updated.max(Some(note.updated_at));
+        }
+        stat
+    };
+
+    let mut stats: Vec<FolderStat> = data
+        .folders
+        .iter()
+        .map(|f| stat_for(Some(&f.id), &f.name))
+        .collect();
+    stats.push(stat_for(None, "No Folder"));
+    stats
+}
+
+// A window of a note's body lines, for loading large notes piecemeal
+pub fn note_body_lines(
+    data: &ParcelData,
+    note_id: &str,
+    start: usize,
+    count: usize,
+) -> anyhow::Result<Vec<String>> {
+    let note = data
+        .notes
+        .iter()
+        .find(|n| n.id == note_id)
+        .ok_or_else(|| anyhow::anyhow!("Note {} not found", note_id))?;
+    Ok(note
+        .body
+        .lines()
+        .skip(start)
+        .take(count)
+        .map(str::to_string)
+        .collect())
+}
+
+// Compile a user-supplied pattern. The regex crate never backtracks, so matching
+// is linear in the input; bounding the compiled size is enough to keep it cheap.
+fn compile_pattern(pattern: &str) -> anyhow::Result<Regex> {
+    RegexBuilder::new(pattern)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .dfa_size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|e| anyhow::anyhow!("Invalid pattern: {}", e))
+}
+
+fn in_scope(note: &Note, note_ids: &Option<Vec<String>>) -> bool {
+    note_ids
+        .as_ref()
+        .is_none_or(|ids| ids.iter().any(|id| id == &note.id))
+}
+
+// Regex find-and-replace across note bodies, limited to `note_ids` when given.
+// Returns how many notes changed.
+pub fn bulk_replace(
+    data: &mut ParcelData,
+    pattern: &str,
+    replacement: &str,
+    note_ids: Option<Vec<String>>,
+    now: u64,
+) -> anyhow::Result<usize> {
+    let re = compile_pattern(pattern)?;
+    let mut changed = 0;
+    for note in data.notes.iter_mut().filter(|n| in_scope(n, &note_ids)) {
+        let replaced = re.replace_all(&note.body, replacement);
+        if replaced != note.body {
+            note.body = replaced.into_owned();
+            note.updated_at = now;
+            changed += 1;
+        }
+    }
+    Ok(changed)
+}
+
+// Match counts per note for a bulk replace, without changing anything.
+// Notes with no matches are left out.
+pub fn preview_bulk_replace(
+    data: &ParcelData,
+    pattern: &str,
+    note_ids: Option<Vec<String>>,
+) -> anyhow::Result<Vec<(String, usize)>> {
+    let re = compile_pattern(pattern)?;
+    Ok(data
+        .notes
+        .iter()
+        .filter(|n| in_scope(n, &note_ids))
+        .map(|n| (n.id.clone(), re.find_iter(&n.body).count()))
+        .filter(|(_, matches)| *matches > 0)
+        .collect())
+}
+
+// Export data to a Word document: folders as Heading 1, notes as Heading 2
+pub fn export_docx(data: &ParcelData) -> anyhow::Result<Vec<u8>> {
+    use docx_rs::{Docx, Paragraph, Run, Style, StyleType};
+
+    fn heading(text: &str, style: &str) -> Paragraph {
+        Paragraph::new().add_run(Run::new().add_text(text)).style(style)
+    }
+
+    fn add_notes(mut doc: Docx, notes: &[&Note], placeholder: &str) -> Docx {
+        for note in notes {
+            doc = doc.add_paragraph(heading(display_title(note, placeholder), "Heading2"));
+            for line in note.body.lines() {
+                doc = doc.add_paragraph(Paragraph::new().add_run(Run::new().add_text(line)));
+            }
+        }
+        doc
+    }
+
+    let mut doc = Docx::new()
+        .add_style(
+            Style::new("Heading1", StyleType::Paragraph)
+                .name("Heading 1")
+                .size(32)
+                .bold(),
+        )
+        .add_style(
+            Style::new("Heading2", StyleType::Paragraph)
+                .name("Heading 2")
+                .size(26)
+                .bold(),
+        );
+
+    for folder in &data.folders {
+        let notes: Vec<&Note> = data
+            .notes
+            .iter()
+            .filter(|n| n.folder_id.as_deref() == Some(folder.id.as_str()))
+            .collect();
+        doc = add_notes(
+            doc.add_paragraph(heading(&folder.name, "Heading1")),
+            &notes,
+            &data.untitled_placeholder,
+        );
+    }
+
+    let unfiled: Vec<&Note> = data.notes.iter().filter(|n| n.folder_id.is_none()).collect();
+    if !unfiled.is_empty() {
+        doc = add_notes(
+            doc.add_paragraph(heading("Notes (No Folder)", "Heading1")),
+            &unfiled,
+            &data.untitled_placeholder,
+        );
+    }
+
+    let mut bytes = std::io::Cursor::new(Vec::new());
+    doc.build().pack(&mut bytes)?;
+    Ok(bytes.into_inner())
+}
+
+// Swatch fill for each of NOTE_COLORS, matching the light theme tints
+fn note_color_rgb(color: &str) -> (f32, f32, f32) {
+    let hex: u32 = match color {
+        "yellow" => 0xfff7c2,
+        "mint" => 0xd8f5e4,
+        "lavender" => 0xeadcff,
+        "salmon" => 0xffd7cf,
+        "sky" => 0xd7ecff,
+        _ => 0xf5f5f0,
+    };
+    let channel = |shift: u32| ((hex >> shift) & 0xff) as f32 / 255.0;
+    (channel(16), channel(8), channel(0))
+}
+
+// Export data to an A4 PDF: folders as section titles, notes as a color swatch
+// and bold title over their wrapped body, flowing onto new pages as needed.
+// Uses the builtin Helvetica faces, so characters outside Latin-1 print as '?'.
+pub fn export_pdf(data: &ParcelData) -> anyhow::Result<Vec<u8>> {
+    use printpdf::{
+        BuiltinFont, Color, IndirectFontRef, Mm, PdfDocument, PdfDocumentReference,
+        PdfLayerReference, Rect, Rgb,
+    };
+
+    const PAGE_WIDTH: f32 = 210.0;
+    const PAGE_HEIGHT: f32 = 297.0;
+    const MARGIN: f32 = 20.0;
+    const SWATCH: f32 = 4.0;
+
+    // Greedy word wrap to `width` characters, which callers pick per font size
+    // to fit the text width in Helvetica
+    fn wrap(text: &str, width: usize) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut line = String::new();
+        for word in text.split_whitespace() {
+            let word: String = word
+                .chars()
+                .map(|c| if matches!(c, ' '..='~' | '\u{a0}'..='\u{ff}') { c } else { '?' })
+                .collect();
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&word);
+        }
+        if !line.is_empty() || lines.is_empty() {
+            lines.push(line);
+        }
+        lines
+    }
+
+    // Points to millimetres with some leading
+    fn line_height(size: f32) -> f32 {
+        size * 0.3528 * 1.4
+    }
+
+    struct Pager {
+        doc: PdfDocumentReference,
+        layer: PdfLayerReference,
+        y: f32,
+    }
+
+    impl Pager {
+        // Starts a new page when `height` more would run into the bottom margin
+        fn reserve(&mut self, height: f32) {
+            if self.y - height < MARGIN {
+                let (page, layer) = self.doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Layer 1");
+                self.layer = self.doc.get_page(page).get_layer(layer);
+                self.y = PAGE_HEIGHT - MARGIN;
+            }
+        }
+
+        fn advance(&mut self, height: f32) {
+            self.reserve(height);
+            self.y -= height;
+        }
+
+        fn text(&mut self, text: &str, size: f32, width: usize, font: &IndirectFontRef, x: f32) {
+            for line in wrap(text, width) {
+                self.advance(line_height(size));
+                self.layer.use_text(line, size, Mm(x), Mm(self.y), font);
+            }
+        }
+    }
+
+    let (doc, page, layer) =
+        PdfDocument::new("Parcel Notes Export", Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Layer 1");
+    let regular = doc.add_builtin_font(BuiltinFont::Helvetica)?;
+    let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold)?;
+    let layer = doc.get_page(page).get_layer(layer);
+    let mut pager = Pager { doc, layer, y: PAGE_HEIGHT - MARGIN };
+
+    pager.text("Parcel Notes Export", 20.0, 45, &bold, MARGIN);
+    for (folder, notes) in notes_by_folder(data) {
+        pager.advance(6.0);
+        let name = folder.map_or("Notes (No Folder)", |f| f.name.as_str());
+        pager.text(name, 16.0, 55, &bold, MARGIN);
+        for note in notes {
+            pager.advance(3.0);
+            // Keep the swatch on the page the title starts on
+            let title_height = line_height(13.0);
+            pager.reserve(title_height);
+            let (r, g, b) = note_color_rgb(&note.color);
+            pager.layer.set_fill_color(Color::Rgb(Rgb::new(r, g, b, None)));
+            pager.layer.add_rect(Rect::new(
+                Mm(MARGIN),
+                Mm(pager.y - title_height),
+                Mm(MARGIN + SWATCH),
+                Mm(pager.y - title_height + SWATCH),
+            ));
+            pager.layer.set_fill_color(Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+            let title = display_title(note, &data.untitled_placeholder);
+            pager.text(title, 13.0, 62, &bold, MARGIN + SWATCH + 2.0);
+            for line in note.body.lines() {
+                pager.text(line, 11.0, 85, &regular, MARGIN);
+            }
+        }
+    }
+
+    Ok(pager.doc.save_to_bytes()?)
+}
+
+// Lists every file in an export bundle so a restore can detect tampering or truncation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BundleManifest {
+    version: u32,
+    entries: Vec<BundleEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BundleEntry {
+    name: String,
+    size: u64,
+    sha256: String,
+}
+
+const BUNDLE_MANIFEST: &str = "manifest.json";
+const BUNDLE_DATA: &str = "notes.json";
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+// Zip archive of the complete data (notes.json), a readable markdown copy, and
+// a manifest with each entry's size and SHA-256
+pub fn export_bundle(data: &ParcelData) -> anyhow::Result<Vec<u8>> {
+    use std::io::Write;
+
+    let files = [
+        (BUNDLE_DATA, export_json(data)?),
+        ("notes.md", export_markdown(data, &ExportOptions::default())?),
+    ];
+    let manifest = BundleManifest {
+        version: 1,
+        entries: files
+            .iter()
+            .map(|(name, content)| BundleEntry {
+                name: name.to_string(),
+                size: content.len() as u64,
+                sha256: sha256_hex(content.as_bytes()),
+            })
+            .collect(),
+    };
+
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options = zip::write::SimpleFileOptions::default();
+    for (name, content) in &files {
+        zip.start_file(*name, options)?;
+        zip.write_all(content.as_bytes())?;
+    }
+    zip.start_file(BUNDLE_MANIFEST, options)?;
+    zip.write_all(serde_json::to_string_pretty(&manifest)?.as_bytes())?;
+    Ok(zip.finish()?.into_inner())
+}
+
+// Zip with a directory per folder holding one `<title-slug>.md` per note, notes
+// without a folder at the top, and an `index.md` linking to every file.
+// Folders are flat, so directories are only ever one level deep.
+pub fn export_tree_zip(data: &ParcelData) -> anyhow::Result<Vec<u8>> {
+    use std::fmt::Write as _;
+    use std::io::Write as _;
+
+    let mut index = String::new();
+    writeln!(index, "# Parcel Notes\n")?;
+    let mut files: Vec<(String, String)> = Vec::new();
+    let mut dirs: Vec<String> = Vec::new();
+    for (folder, notes) in notes_by_folder(data) {
+        let dir = match folder {
+            Some(folder) => {
+                let stem = match slugify(&folder.name) {
+                    stem if stem.is_empty() => folder.id.clone(),
+                    stem => stem,
+                };
+                let mut dir = stem.clone();
+                let mut n = 2;
+                while dirs.contains(&dir) {
+                    dir = format!("{}-{}", stem, n);
+                    n += 1;
+                }
+                dirs.push(dir.clone());
+                writeln!(index, "## {}\n", folder.name)?;
+                format!("{}/", dir)
+            }
+            None => {
+                writeln!(index, "## Notes (No Folder)\n")?;
+                String::new()
+            }
+        };
+        for note in notes {
+            let title = display_title(note, &data.untitled_placeholder);
+            let stem = match slugify(&note.title) {
+                stem if stem.is_empty() => note.id.clone(),
+                stem => stem,
+            };
+            let name = unique_file_name(&stem, "md", |f| {
+                let path = format!("{}{}", dir, f);
+                path == "index.md" || files.iter().any(|(taken, _)| *taken == path)
+            });
+            let path = format!("{}{}", dir, name);
+            writeln!(index, "- [{}]({})", title, path)?;
+            files.push((path, format!("# {}\n\n{}\n", title, note.body)));
+        }
+        writeln!(index)?;
+    }
+
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options = zip::write::SimpleFileOptions::default();
+    zip.start_file("index.md", options)?;
+    zip.write_all(index.as_bytes())?;
+    for (path, content) in &files {
+        zip.start_file(path.as_str(), options)?;
+        zip.write_all(content.as_bytes())?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+// Check every manifest entry against the archive, then read the data the same
+// way `load` does. Nothing is imported from a bundle that fails verification.
+pub fn read_bundle(bytes: &[u8]) -> anyhow::Result<ParcelData> {
+    use std::io::Read;
+
+    let mut zip = zip::ZipArchive::new(std::io::Cursor::new(bytes))
+        .map_err(|e| anyhow::anyhow!("Not a valid bundle: {}", e))?;
+    let mut read_entry = |name: &str| -> anyhow::Result<Vec<u8>> {
+        let mut file = zip
+            .by_name(name)
+            .map_err(|_| anyhow::anyhow!("Bundle is missing {}", name))?;
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)
+            .map_err(|e| anyhow::anyhow!("Bundle entry {} is corrupt: {}", name, e))?;
+        Ok(content)
+    };
+
+    let manifest: BundleManifest = serde_json::from_slice(&read_entry(BUNDLE_MANIFEST)?)
+        .map_err(|e| anyhow::anyhow!("Failed to parse bundle manifest: {}", e))?;
+    if !manifest.entries.iter().any(|e| e.name == BUNDLE_DATA) {
+        return Err(anyhow::anyhow!("Bundle manifest doesn't list {}", BUNDLE_DATA));
+    }
+    let mut data_bytes = Vec::new();
+    for entry in &manifest.entries {
+        let content = read_entry(&entry.name)?;
+        if content.len() as u64 != entry.size || sha256_hex(&content) != entry.sha256 {
+            return Err(anyhow::anyhow!(
+                "Bundle entry {} doesn't match its manifest hash",
+                entry.name
+            ));
+        }
+        if entry.name == BUNDLE_DATA {
+            data_bytes = content;
+        }
+    }
+
+    let s = std::str::from_utf8(&data_bytes)?;
+    let data: ParcelData = serde_json::from_str(strip_bom(s).0)
+        .map_err(|e| anyhow::anyhow!("Failed to parse JSON: {}. File may be corrupt.", e))?;
+    check_import_version(&data)?;
+    checked_data(data)
+}
+
+// Every format `export` can produce
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    Json,
+    Csv,
+    Plaintext,
+    Opml,
+    Org,
+    Docx,
+    Pdf,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "content", rename_all = "lowercase")]
+pub enum ExportOutput {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+// Single entry point for the document exports. Header and footer apply to the
+// markdown, HTML and plaintext formats; the others ignore options.
+pub fn export(
+    data: &ParcelData,
+    format: ExportFormat,
+    options: &ExportOptions,
+) -> anyhow::Result<ExportOutput> {
+    Ok(match format {
+        ExportFormat::Markdown => ExportOutput::Text(export_markdown(data, options)?),
+        ExportFormat::Html => ExportOutput::Text(export_html(data, options)?),
+        ExportFormat::Json => ExportOutput::Text(export_json(data)?),
+        ExportFormat::Csv => ExportOutput::Text(export_csv(data)?),
+        ExportFormat::Plaintext => ExportOutput::Text(export_plaintext(data, options)?),
+        ExportFormat::Opml => ExportOutput::Text(export_opml(data)?),
+        ExportFormat::Org => ExportOutput::Text(export_org(data)?),
+        ExportFormat::Docx => ExportOutput::Bytes(export_docx(data)?),
+        ExportFormat::Pdf => ExportOutput::Bytes(export_pdf(data)?),
+    })
+}
+
+// Rough byte size of `export` output for the default options, from title and
+// body lengths plus fixed per-document, per-folder, and per-note overheads.
+// Meant for warning before big exports, not for allocating buffers.
+pub fn estimate_export_size(data: &ParcelData, format: ExportFormat) -> usize {
+    // (document, per folder, per note) overhead in bytes
+    let (document, per_folder, per_note) = match format {
+        ExportFormat::Markdown => (20, 8, 60),
+        ExportFormat::Html => (400, 20, 120),
+        ExportFormat::Json => (400, 100, 260),
+        ExportFormat::Csv => (80, 0, 70),
+        ExportFormat::Plaintext => (0, 30, 30),
+        ExportFormat::Opml => (200, 40, 60),
+        ExportFormat::Org => (20, 10, 120),
+        // Mostly the fixed styles and package parts docx-rs writes
+        ExportFormat::Docx => (18000, 60, 250),
+        // Fonts, page tree, and per-line text operators; bodies are written uncompressed
+        ExportFormat::Pdf => (1500, 60, 200),
+    };
+    let text: usize = data.notes.iter().map(|n| n.title.len() + n.body.len()).sum::<usize>()
+        + data.folders.iter().map(|f| f.name.len()).sum::<usize>();
+    document + per_folder * (data.folders.len() + 1) + per_note * data.notes.len() + text
+}
+
+// Escapes text for HTML and XML content and attribute values
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Notes of a folder in list order, followed by unfiled notes under `None`
+fn notes_by_folder(data: &ParcelData) -> Vec<(Option<&Folder>, Vec<&Note>)> {
+    let mut groups: Vec<(Option<&Folder>, Vec<&Note>)> = data
+        .folders
+        .iter()
+        .map(|folder| {
+            let notes = data
+                .notes
+                .iter()
+                .filter(|n| n.folder_id.as_deref() == Some(folder.id.as_str()))
+                .collect();
+            (Some(folder), notes)
+        })
+        .collect();
+    let unfiled: Vec<&Note> = data.notes.iter().filter(|n| n.folder_id.is_none()).collect();
+    if !unfiled.is_empty() {
+        groups.push((None, unfiled));
+    }
+    groups
+}
+
+// Standalone HTML page laid out like the markdown export. Body paragraphs are
+// split on blank lines; a separator option becomes an <hr> between notes.
+pub fn export_html(data: &ParcelData, options: &ExportOptions) -> anyhow::Result<String> {
+    use std::fmt::Write;
+
+    let data = &*export_data(data, options);
+    let mut output = String::new();
+    writeln!(output, "<!DOCTYPE html>")?;
+    writeln!(output, "<html>\n<head>\n<meta charset=\"utf-8\">")?;
+    writeln!(output, "<title>Parcel Notes Export</title>\n</head>\n<body>")?;
+    if let Some(header) = &options.header {
+        writeln!(output, "{}", header)?;
+    }
+    writeln!(output, "<h1>Parcel Notes Export</h1>")?;
+    writeln!(
+        output,
+        "<p><em>Total notes: {}</em><br>\n<em>Total folders: {}</em></p>",
+        data.notes.len(),
+        data.folders.len()
+    )?;
+
+    for (folder, notes) in notes_by_folder(data) {
+        match folder {
+            Some(folder) => writeln!(output, "<h2>Folder: {}</h2>", xml_escape(&folder.name))?,
+            None => writeln!(output, "<h2>Notes (No Folder)</h2>")?,
+        }
+        for (i, note) in notes.iter().enumerate() {
+            if i > 0 && options.separator.is_some() {
+                writeln!(output, "<hr>")?;
+            }
+            writeln!(output, "<article>")?;
+            writeln!(
+                output,
+                "<h3>{}</h3>",
+                xml_escape(display_title(note, &data.untitled_placeholder))
+            )?;
+            let body = export_body(note, options);
+            for paragraph in body.split("\n\n").filter(|p| !p.trim().is_empty()) {
+                let lines: Vec<String> = paragraph.lines().map(xml_escape).collect();
+                writeln!(output, "<p>{}</p>", lines.join("<br>\n"))?;
+            }
+            if options.word_count {
+                writeln!(
+                    output,
+                    "<p class=\"meta\"><em>Color: {} | Pinned: {} | Words: {}</em></p>",
+                    note.color,
+                    note.pinned,
+                    word_count(&note.body)
+                )?;
+            } else {
+                writeln!(
+                    output,
+                    "<p class=\"meta\"><em>Color: {} | Pinned: {}</em></p>",
+                    note.color, note.pinned
+                )?;
+            }
+            writeln!(output, "</article>")?;
+        }
+    }
+
+    if let Some(footer) = &options.footer {
+        writeln!(output, "{}", footer)?;
+    }
+    writeln!(output, "</body>\n</html>")?;
+    Ok(output)
+}
+
+// One row per note with a header `import_csv` understands
+pub fn export_csv(data: &ParcelData) -> anyhow::Result<String> {
+    let mut writer = ::csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "id", "title", "body", "color", "folder", "pinned", "tags", "createdAt", "updatedAt",
+    ])?;
+    for note in &data.notes {
+        let folder = note
+            .folder_id
+            .as_deref()
+            .and_then(|id| data.folders.iter().find(|f| f.id == id))
+            .map_or("", |f| f.name.as_str());
+        writer.write_record([
+            note.id.as_str(),
+            note.title.as_str(),
+            note.body.as_str(),
+            note.color.as_str(),
+            folder,
+            if note.pinned { "true" } else { "false" },
+            note.tags.join(";").as_str(),
+            note.created_at.to_string().as_str(),
+            note.updated_at.to_string().as_str(),
+        ])?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+// Titles underlined with dashes followed by the body, grouped by folder
+pub fn export_plaintext(data: &ParcelData, options: &ExportOptions) -> anyhow::Result<String> {
+    use std::fmt::Write;
+
+    let mut output = String::new();
+    if let Some(header) = &options.header {
+        writeln!(output, "{}\n", header)?;
+    }
+    for (folder, notes) in notes_by_folder(data) {
+        let heading = folder.map_or("Notes (No Folder)", |f| f.name.as_str());
+        writeln!(output, "{}\n{}\n", heading, "=".repeat(heading.chars().count()))?;
+        for (i, note) in notes.iter().enumerate() {
+            if let Some(separator) = options.separator.as_ref().filter(|_| i > 0) {
+                writeln!(output, "{}\n", separator)?;
+            }
+            let title = display_title(note, &data.untitled_placeholder);
+            writeln!(output, "{}\n{}\n", title, "-".repeat(title.chars().count()))?;
+            if !note.body.is_empty() {
+                writeln!(output, "{}\n", export_body(note, options))?;
+            }
+        }
+    }
+    if let Some(footer) = &options.footer {
+        writeln!(output, "{}", footer)?;
+    }
+    Ok(output)
+}
+
+// Org-mode document: folders as `*` headings, notes as `**` headings with a
+// properties drawer. Body lines starting with `*` get a leading space so they
+// don't turn into headings.
+pub fn export_org(data: &ParcelData) -> anyhow::Result<String> {
+    use std::fmt::Write;
+
+    let mut output = String::new();
+    writeln!(output, "#+TITLE: Parcel Notes Export\n")?;
+    for (folder, notes) in notes_by_folder(data) {
+        writeln!(output, "* {}", folder.map_or("Notes (No Folder)", |f| f.name.as_str()))?;
+        for note in notes {
+            writeln!(output, "** {}", display_title(note, &data.untitled_placeholder))?;
+            writeln!(output, ":PROPERTIES:")?;
+            writeln!(output, ":ID: {}", note.id)?;
+            writeln!(output, ":COLOR: {}", note.color)?;
+            writeln!(output, ":PINNED: {}", note.pinned)?;
+            writeln!(output, ":END:")?;
+            for line in note.body.lines() {
+                if line.starts_with('*') {
+                    writeln!(output, " {}", line)?;
+                } else {
+                    writeln!(output, "{}", line)?;
+                }
+            }
+        }
+    }
+    Ok(output)
+}
+
+pub fn set_reminder(data: &mut ParcelData, note_id: &str, at: Option<u64>) -> anyhow::Result<()> {
+    let note = data
+        .notes
+        .iter_mut()
+        .find(|n| n.id == note_id)
+        .ok_or_else(|| anyhow::anyhow!("Note {} not found", note_id))?;
+    note.reminder_at = at;
+    Ok(())
+}
+
+// TEXT value escaping from RFC 5545 section 3.3.11
+fn ics_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+// A content line folded at 75 octets, as RFC 5545 requires, with CRLF endings
+fn ics_line(output: &mut String, line: &str) {
+    let mut start = 0;
+    let mut limit = 75;
+    for (i, c) in line.char_indices() {
+        if i + c.len_utf8() - start > limit {
+            output.push_str(&line[start..i]);
+            output.push_str("\r\n ");
+            start = i;
+            // The leading space counts toward the next line's 75
+            limit = 74;
+        }
+    }
+    output.push_str(&line[start..]);
+    output.push_str("\r\n");
+}
+
+fn ics_time(ms: u64) -> anyhow::Result<String> {
+    let time = i64::try_from(ms)
+        .ok()
+        .and_then(DateTime::from_timestamp_millis)
+        .ok_or_else(|| anyhow::anyhow!("Time {} out of range", ms))?;
+    Ok(time.format("%Y%m%dT%H%M%SZ").to_string())
+}
+
+// iCalendar file with a VEVENT per note that has a reminder, starting at the
+// reminder. Notes without one are left out.
+pub fn export_ics(data: &ParcelData) -> anyhow::Result<String> {
+    let mut output = String::new();
+    ics_line(&mut output, "BEGIN:VCALENDAR");
+    ics_line(&mut output, "VERSION:2.0");
+    ics_line(&mut output, "PRODID:-//Parcel//Parcel Notes//EN");
+    for note in &data.notes {
+        let Some(at) = note.reminder_at else {
+            continue;
+        };
+        ics_line(&mut output, "BEGIN:VEVENT");
+        ics_line(&mut output, &format!("UID:{}@parcel", note.id));
+        ics_line(&mut output, &format!("DTSTAMP:{}", ics_time(note.updated_at)?));
+        ics_line(&mut output, &format!("DTSTART:{}", ics_time(at)?));
+        let title = display_title(note, &data.untitled_placeholder);
+        ics_line(&mut output, &format!("SUMMARY:{}", ics_escape(title)));
+        if !note.body.is_empty() {
+            ics_line(&mut output, &format!("DESCRIPTION:{}", ics_escape(&note.body)));
+        }
+        ics_line(&mut output, "END:VEVENT");
+    }
+    ics_line(&mut output, "END:VCALENDAR");
+    Ok(output)
+}
+
+// OPML 2.0 outline: folders containing their notes, unfiled notes at the top
+// level. Bodies go in the `_note` attribute.
+pub fn export_opml(data: &ParcelData) -> anyhow::Result<String> {
+    use std::fmt::Write;
+
+    fn note_outline(
+        output: &mut String,
+        note: &Note,
+        placeholder: &str,
+        indent: &str,
+    ) -> std::fmt::Result {
+        writeln!(
+            output,
+            "{}<outline text=\"{}\" _note=\"{}\"/>",
+            indent,
+            xml_escape(display_title(note, placeholder)),
+            xml_escape(&note.body).replace('\n', "&#10;")
+        )
+    }
+
+    let mut output = String::new();
+    writeln!(output, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(output, "<opml version=\"2.0\">")?;
+    writeln!(output, "  <head>\n    <title>Parcel Notes Export</title>\n  </head>")?;
+    writeln!(output, "  <body>")?;
+    for (folder, notes) in notes_by_folder(data) {
+        match folder {
+            Some(folder) => {
+                writeln!(output, "    <outline text=\"{}\">", xml_escape(&folder.name))?;
+                for note in notes {
+                    note_outline(&mut output, note, &data.untitled_placeholder, "      ")?;
+                }
+                writeln!(output, "    </outline>")?;
+            }
+            None => {
+                for note in notes {
+                    note_outline(&mut output, note, &data.untitled_placeholder, "    ")?;
+                }
+            }
+        }
+    }
+    writeln!(output, "  </body>\n</opml>")?;
+    Ok(output)
+}
+
+// Import a plain-text file as one note. Without an explicit title, the first
+// non-empty line becomes the title.
+pub fn import_plaintext(
+    data: &mut ParcelData,
+    text: &str,
+    title: Option<String>,
+    folder_id: Option<String>,
+    now: u64,
+    id: String,
+) -> anyhow::Result<Note> {
+    if let Some(folder_id) = &folder_id {
+        if !data.folders.iter().any(|f| &f.id == folder_id) {
+            return Err(anyhow::anyhow!("Folder {} not found", folder_id));
+        }
+    }
+    check_body_len(data, text)?;
+    let title = title
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .or_else(|| {
+            text.lines()
+                .map(str::trim)
+                .find(|l| !l.is_empty())
+                .map(str::to_string)
+        })
+        .unwrap_or_default();
+    let note = Note {
+        id,
+        title,
+        body: text.to_string(),
+        folder_id,
+        pinned: false,
+        color: "paper".to_string(),
+        created_at: now,
+        updated_at: now,
+        ..Default::default()
+    };
+    data.notes.insert(0, note.clone());
+    Ok(note)
+}
+
+// Distinct folder names starting with `prefix` (case-insensitive), alphabetical.
+// A non-empty prefix returns at most MAX_FOLDER_SUGGESTIONS names.
+pub fn folder_names(data: &ParcelData, prefix: &str) -> Vec<String> {
+    let prefix = prefix.to_lowercase();
+    let mut names: Vec<String> = data
+        .folders
+        .iter()
+        .filter(|f| f.name.to_lowercase().starts_with(&prefix))
+        .map(|f| f.name.clone())
+        .collect();
+    names.sort_by_key(|n| n.to_lowercase());
+    names.dedup();
+    if !prefix.is_empty() {
+        names.truncate(MAX_FOLDER_SUGGESTIONS);
+    }
+    names
+}
+
+// Folders ordered by their most recently updated note (or the folder itself when empty), newest first
+pub fn folders_by_activity(data: &ParcelData) -> Vec<Folder> {
+    let last_activity = |folder: &Folder| {
+        data.notes
+            .iter()
+            .filter(|n| n.folder_id.as_deref() == Some(folder.id.as_str()))
+            .map(|n| n.updated_at)
+            .max()
+            .unwrap_or(folder.updated_at)
+    };
+    let mut folders = data.folders.clone();
+    folders.sort_by_cached_key(|f| std::cmp::Reverse(last_activity(f)));
+    folders
+}
+
+// Estimated minutes to read a note's body, never less than one
+pub fn reading_time(note: &Note, wpm: usize) -> usize {
+    let wpm = if wpm == 0 { DEFAULT_WPM } else { wpm };
+    word_count(&note.body).div_ceil(wpm).max(1)
+}
+
+// Per-note checks for the editor, run before saving. Returns a list of
+// human-readable issues; empty means the note is fine.
+pub fn validate_note(note: &Note) -> Vec<String> {
+    let mut issues = Vec::new();
+    if note.id.is_empty() {
+        issues.push("Note has an empty ID".to_string());
+    }
+    if !NOTE_COLORS.contains(&note.color.as_str()) {
+        issues.push(format!(
+            "Invalid color: {}. It will be reset to paper.",
+            note.color
+        ));
+    }
+    if note.title.trim() != note.title {
+        issues.push(format!(
+            "Title has surrounding whitespace; it reads as \"{}\"",
+            note.title.trim()
+        ));
+    }
+    if note.body.len() > LARGE_BODY_BYTES {
+        issues.push(format!(
+            "Body is {} KB, which may slow down saving",
+            note.body.len() / 1024
+        ));
+    }
+    issues
+}
+
+fn check_body_len(data: &ParcelData, body: &str) -> anyhow::Result<()> {
+    if let Some(max) = data.max_body_len {
+        let len = body.chars().count();
+        if len > max {
+            return Err(ParcelError::TooLarge { len, max }.into());
+        }
+    }
+    Ok(())
+}
+
+// Pins, colors, folders and tags are metadata: changing them only moves
+// updated_at when touch_on_metadata is on. Takes the setting rather than the
+// data so it can be called with the note borrowed.
+fn touch_metadata(note: &mut Note, touch_on_metadata: bool, now: u64) {
+    if touch_on_metadata {
+        note.updated_at = now;
+    }
+}
+
+// Apply every field set in `patch` at once. Everything is validated before
+// anything changes, so a bad color or folder leaves the note untouched.
+// updated_at only moves for metadata-only patches when touch_on_metadata is on.
+pub fn patch_note(
+    data: &mut ParcelData,
+    note_id: &str,
+    patch: NotePatch,
+    now: u64,
+) -> anyhow::Result<Note> {
+    if let Some(color) = &patch.color {
+        if !NOTE_COLORS.contains(&color.as_str()) {
+            return Err(anyhow::anyhow!("Invalid color: {}", color));
+        }
+    }
+    if let Some(Some(folder_id)) = &patch.folder_id {
+        if !data.folders.iter().any(|f| &f.id == folder_id) {
+            return Err(anyhow::anyhow!("Folder {} not found", folder_id));
+        }
+    }
+    if let Some(body) = &patch.body {
+        check_body_len(data, body)?;
+    }
+    let edits_content = patch.title.is_some() || patch.body.is_some();
+    let touch_on_metadata = data.touch_on_metadata;
+    let note = data
+        .notes
+        .iter_mut()
+        .find(|n| n.id == note_id)
+        .ok_or_else(|| anyhow::anyhow!("Note {} not found", note_id))?;
+    if let Some(title) = patch.title {
+        note.title = title;
+    }
+    if let Some(body) = patch.body {
+        note.body = body;
+    }
+    if let Some(color) = patch.color {
+        note.color = color;
+    }
+    if let Some(folder_id) = patch.folder_id {
+        note.folder_id = folder_id;
+    }
+    if let Some(pinned) = patch.pinned {
+        if pinned != note.pinned {
+            note.pinned_at = pinned.then_some(now);
+        }
+        note.pinned = pinned;
+    }
+    if let Some(tags) = patch.tags {
+        note.tags = normalized_tags(&tags);
+    }
+    touch_metadata(note, edits_content || touch_on_metadata, now);
+    Ok(note.clone())
+}
+
+// Replace a note's title and/or body
+pub fn update_note(
+    data: &mut ParcelData,
+    note_id: &str,
+    title: Option<String>,
+    body: Option<String>,
+    now: u64,
+) -> anyhow::Result<Note> {
+    if let Some(body) = &body {
+        check_body_len(data, body)?;
+    }
+    let note = data
+        .notes
+        .iter_mut()
+        .find(|n| n.id == note_id)
+        .ok_or_else(|| anyhow::anyhow!("Note {} not found", note_id))?;
+    if let Some(title) = title {
+        note.title = title;
+    }
+    if let Some(body) = body {
+        note.body = body;
+    }
+    note.updated_at = now;
+    Ok(note.clone())
+}
+
+// Add text to the end of a note's body on a new line
+pub fn append_to_note(
+    data: &mut ParcelData,
+    note_id: &str,
+    text: &str,
+    now: u64,
+) -> anyhow::Result<Note> {
+    let note = data
+        .notes
+        .iter()
+        .find(|n| n.id == note_id)
+        .ok_or_else(|| anyhow::anyhow!("Note {} not found", note_id))?;
+    let mut body = note.body.clone();
+    if !body.is_empty() && !body.ends_with('\n') {
+        body.push('\n');
+    }
+    body.push_str(text);
+    update_note(data, note_id, None, Some(body), now)
+}
+
+// Flat outline: each folder followed by its notes one level deeper, then
+// unfiled notes at the top level
+pub fn outline(data: &ParcelData) -> Vec<OutlineEntry> {
+    let note_entry = |note: &Note, depth| OutlineEntry {
+        id: note.id.clone(),
+        title: display_title(note, &data.untitled_placeholder).to_string(),
+        depth,
+        kind: "note".to_string(),
+    };
+    let mut entries = Vec::new();
+    for folder in &data.folders {
+        entries.push(OutlineEntry {
+            id: folder.id.clone(),
+            title: folder.name.clone(),
+            depth: 0,
+            kind: "folder".to_string(),
+        });
+        entries.extend(
+            data.notes
+                .iter()
+                .filter(|n| n.folder_id.as_deref() == Some(folder.id.as_str()))
+                .map(|n| note_entry(n, 1)),
+        );
+    }
+    entries.extend(
+        data.notes
+            .iter()
+            .filter(|n| n.folder_id.is_none())
+            .map(|n| note_entry(n, 0)),
+    );
+    entries
+}
+
+// Markdown index of tags, each listing links to the notes that carry it
+pub fn export_tags_index(data: &ParcelData) -> anyhow::Result<String> {
+    use std::fmt::Write;
+
+    let mut by_tag: std::collections::BTreeMap<&str, Vec<&Note>> = Default::default();
+    for note in &data.notes {
+        for tag in &note.tags {
+            let notes = by_tag.entry(tag.as_str()).or_default();
+            if !notes.iter().any(|n| n.id == note.id) {
+                notes.push(note);
+            }
+        }
+    }
+
+    let mut output = String::new();
+    writeln!(output, "# Tags\n")?;
+    for (tag, notes) in by_tag {
+        writeln!(output, "## {}\n", tag)?;
+        for note in notes {
+            writeln!(output, "- [[{}]]", display_title(note, &data.untitled_placeholder))?;
+        }
+        writeln!(output)?;
+    }
+    Ok(output)
+}
+
+// Ids of live (unarchived) notes under each of their tags
+pub fn notes_by_tag(data: &ParcelData) -> HashMap<String, Vec<String>> {
+    let mut by_tag: HashMap<String, Vec<String>> = HashMap::new();
+    for note in data.notes.iter().filter(|n| !n.archived) {
+        for tag in &note.tags {
+            let ids = by_tag.entry(tag.clone()).or_default();
+            if !ids.contains(&note.id) {
+                ids.push(note.id.clone());
+            }
+        }
+    }
+    by_tag
+}
+
+// One checklist section per color in palette order; colors without notes are left out
+pub fn export_kanban_markdown(data: &ParcelData) -> anyhow::Result<String> {
+    use std::fmt::Write;
+
+    let mut output = String::new();
+    writeln!(output, "# Board\n")?;
+    for color in NOTE_COLORS {
+        let mut notes = data.notes.iter().filter(|n| n.color == color).peekable();
+        if notes.peek().is_none() {
+            continue;
+        }
+        writeln!(output, "## {}\n", color)?;
+        for note in notes {
+            writeln!(output, "- [ ] {}", display_title(note, &data.untitled_placeholder))?;
+        }
+        writeln!(output)?;
+    }
+    Ok(output)
+}
+
+// Ids of the longest and shortest notes by body word count; ties go to the
+// most recently updated note
+pub fn note_extremes(data: &ParcelData) -> (Option<String>, Option<String>) {
+    let longest = data
+        .notes
+        .iter()
+        .max_by_key(|n| (word_count(&n.body), n.updated_at))
+        .map(|n| n.id.clone());
+    let shortest = data
+        .notes
+        .iter()
+        .max_by_key(|n| (std::cmp::Reverse(word_count(&n.body)), n.updated_at))
+        .map(|n| n.id.clone());
+    (longest, shortest)
+}
+
+// Delete notes by id. Protected notes are kept unless `force` is set.
+pub fn delete_notes(data: &mut ParcelData, note_ids: &[String], force: bool) -> DeleteReport {
+    let mut report = DeleteReport {
+        deleted: 0,
+        skipped: 0,
+    };
+    data.notes.retain(|n| {
+        if !note_ids.contains(&n.id) {
+            return true;
+        }
+        if n.protected && !force {
+            report.skipped += 1;
+            return true;
+        }
+        report.deleted += 1;
+        false
+    });
+    report
+}
+
+// The frontend saves by sending every note it has, so one it dropped looks
+// deleted. Protected notes only go through `delete_notes` with force; put back
+// any the payload is missing, at their stored position. Returns how many.
+pub fn keep_protected_notes(data: &mut ParcelData, stored: &ParcelData) -> usize {
+    let mut kept = 0;
+    for (i, note) in stored.notes.iter().enumerate().filter(|(_, n)| n.protected) {
+        if !data.notes.iter().any(|n| n.id == note.id) {
+            data.notes.insert(i.min(data.notes.len()), note.clone());
+            kept += 1;
+        }
+    }
+    kept
+}
+
+// Keep `previous` as a revision of `note` if the edit changed its title or
+// body, dropping the oldest beyond `max_revisions`
+pub fn push_revision(note: &mut Note, previous: &Note, max_revisions: u32) {
+    if note.title == previous.title && note.body == previous.body {
+        return;
+    }
+    note.revisions.push(NoteRevision {
+        title: previous.title.clone(),
+        body: previous.body.clone(),
+        updated_at: previous.updated_at,
+    });
+    trim_revisions(note, max_revisions);
+}
+
+fn trim_revisions(note: &mut Note, max_revisions: u32) {
+    let excess = note.revisions.len().saturating_sub(max_revisions as usize);
+    note.revisions.drain(..excess);
+}
+
+// Trim every note's revisions to the current limit; runs on every save, so a
+// lowered limit applies to existing history too
+pub fn compact(data: &mut ParcelData) {
+    let max_revisions = data.max_revisions;
+    for note in data.notes.iter_mut() {
+        trim_revisions(note, max_revisions);
+    }
+}
+
+// The frontend doesn't track revisions, so take the stored history and record
+// the stored version of every note the save changes
+pub fn carry_over_revisions(data: &mut ParcelData, stored: &ParcelData) {
+    let by_id: HashMap<&str, &Note> = stored.notes.iter().map(|n| (n.id.as_str(), n)).collect();
+    for note in data.notes.iter_mut() {
+        if let Some(previous) = by_id.get(note.id.as_str()) {
+            note.revisions = previous.revisions.clone();
+            push_revision(note, previous, stored.max_revisions);
+        }
+    }
+}
+
+pub fn set_protected(data: &mut ParcelData, note_id: &str, protected: bool) -> anyhow::Result<()> {
+    let note = data
+        .notes
+        .iter_mut()
+        .find(|n| n.id == note_id)
+        .ok_or_else(|| anyhow::anyhow!("Note {} not found", note_id))?;
+    note.protected = protected;
+    Ok(())
+}
+
+pub fn set_pinned(data: &mut ParcelData, note_id: &str, pinned: bool, now: u64) -> anyhow::Result<()> {
+    let touch_on_metadata = data.touch_on_metadata;
+    let note = data
+        .notes
+        .iter_mut()
+        .find(|n| n.id == note_id)
+        .ok_or_else(|| anyhow::anyhow!("Note {} not found", note_id))?;
+    if pinned && !note.pinned {
+        note.pinned_at = Some(now);
+    } else if !pinned {
+        note.pinned_at = None;
+    }
+    if pinned != note.pinned {
+        touch_metadata(note, touch_on_metadata, now);
+    }
+    note.pinned = pinned;
+    Ok(())
+}
+
+pub fn set_color_locked(data: &mut ParcelData, note_id: &str, locked: bool) -> anyhow::Result<()> {
+    let note = data
+        .notes
+        .iter_mut()
+        .find(|n| n.id == note_id)
+        .ok_or_else(|| anyhow::anyhow!("Note {} not found", note_id))?;
+    note.color_locked = locked;
+    Ok(())
+}
+
+// Bulk recolors skip color-locked notes and return how many notes changed color
+fn recolor_where(
+    data: &mut ParcelData,
+    color: &str,
+    now: u64,
+    matches: impl Fn(&Note) -> bool,
+) -> anyhow::Result<usize> {
+    if !NOTE_COLORS.contains(&color) {
+        return Err(anyhow::anyhow!("Invalid color: {}", color));
+    }
+    let touch_on_metadata = data.touch_on_metadata;
+    let mut changed = 0;
+    for note in data.notes.iter_mut().filter(|n| !n.color_locked && matches(n)) {
+        if note.color != color {
+            note.color = color.to_string();
+            touch_metadata(note, touch_on_metadata, now);
+            changed += 1;
+        }
+    }
+    Ok(changed)
+}
+
+pub fn recolor_all(data: &mut ParcelData, color: &str, now: u64) -> anyhow::Result<usize> {
+    recolor_where(data, color, now, |_| true)
+}
+
+pub fn recolor_folder_notes(
+    data: &mut ParcelData,
+    folder_id: &str,
+    color: &str,
+    now: u64,
+) -> anyhow::Result<usize> {
+    if !data.folders.iter().any(|f| f.id == folder_id) {
+        return Err(anyhow::anyhow!("Folder {} not found", folder_id));
+    }
+    recolor_where(data, color, now, |n| n.folder_id.as_deref() == Some(folder_id))
+}
+
+// Tags are compared normalized, so "Work" and " work" recolor the same notes
+pub fn color_notes_by_tag(
+    data: &mut ParcelData,
+    tag: &str,
+    color: &str,
+    now: u64,
+) -> anyhow::Result<usize> {
+    let tag = normalize_tag(tag);
+    recolor_where(data, color, now, |n| n.tags.iter().any(|t| normalize_tag(t) == tag))
+}
+
+// A closed ``` fenced block. `language` is the fence's info string, if any.
+struct CodeBlock {
+    language: Option<String>,
+    code: String,
+    // Lines inside the fence, not counting the fence lines themselves
+    lines: usize,
+}
+
+fn code_blocks(body: &str) -> Vec<CodeBlock> {
+    let mut blocks = Vec::new();
+    let mut open: Option<CodeBlock> = None;
+    for line in body.lines() {
+        let trimmed = line.trim_start();
+        match open.take() {
+            None => {
+                if let Some(info) = trimmed.strip_prefix("```") {
+                    let info = info.trim();
+                    open = Some(CodeBlock {
+                        language: (!info.is_empty()).then(|| info.to_string()),
+                        code: String::new(),
+                        lines: 0,
+                    });
+                }
+            }
+            Some(block) if trimmed.starts_with("```") => blocks.push(block),
+            Some(mut block) => {
+                block.code.push_str(line);
+                block.code.push('\n');
+                block.lines += 1;
+                open = Some(block);
+            }
+        }
+    }
+    blocks
+}
+
+// Likely rendering problems in a body: a ``` fence that's never closed, and,
+// outside code fences, [[ and ]] that don't pair up or an odd number of **
+fn markdown_issues(body: &str) -> Vec<String> {
+    let mut issues = Vec::new();
+    let mut open_fence: Option<usize> = None;
+    let (mut opening, mut closing, mut bold) = (0, 0, 0);
+    for (i, line) in body.lines().enumerate() {
+        if line.trim_start().starts_with("```") {
+            open_fence = match open_fence {
+                Some(_) => None,
+                None => Some(i + 1),
+            };
+            continue;
+        }
+        if open_fence.is_none() {
+            opening += line.matches("[[").count();
+            closing += line.matches("]]").count();
+            bold += line.matches("**").count();
+        }
+    }
+    if let Some(line) = open_fence {
+        issues.push(format!("Unclosed code fence opened on line {}", line));
+    }
+    if opening != closing {
+        issues.push(format!("Unbalanced links: {} [[ but {} ]]", opening, closing));
+    }
+    if bold % 2 == 1 {
+        issues.push("Unmatched ** (bold is never closed)".to_string());
+    }
+    issues
+}
+
+// Each note with markdown that will likely render badly, with what's wrong
+pub fn notes_with_markdown_issues(data: &ParcelData) -> Vec<(String, Vec<String>)> {
+    data.notes
+        .iter()
+        .map(|n| (n.id.clone(), markdown_issues(&n.body)))
+        .filter(|(_, issues)| !issues.is_empty())
+        .collect()
+}
+
+// File extensions for common fence languages; anything else exports as .txt
+const SNIPPET_EXTENSIONS: [(&str, &str); 16] = [
+    ("rust", "rs"),
+    ("rs", "rs"),
+    ("python", "py"),
+    ("py", "py"),
+    ("javascript", "js"),
+    ("js", "js"),
+    ("typescript", "ts"),
+    ("ts", "ts"),
+    ("tsx", "tsx"),
+    ("json", "json"),
+    ("bash", "sh"),
+    ("sh", "sh"),
+    ("go", "go"),
+    ("html", "html"),
+    ("css", "css"),
+    ("sql", "sql"),
+];
+
+// Lowercase alphanumeric runs joined by dashes, for file names
+fn slugify(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+// `stem.ext`, or `stem-2.ext`, `stem-3.ext`... when the name is already taken
+fn unique_file_name(stem: &str, extension: &str, taken: impl Fn(&str) -> bool) -> String {
+    let mut filename = format!("{}.{}", stem, extension);
+    let mut n = 2;
+    while taken(&filename) {
+        filename = format!("{}-{}.{}", stem, n, extension);
+        n += 1;
+    }
+    filename
+}
+
+// (filename, language, content) for every note that is mostly one code fence:
+// a single block holding more lines than the text around it
+pub fn export_snippets(data: &ParcelData) -> anyhow::Result<Vec<(String, String, String)>> {
+    let mut snippets: Vec<(String, String, String)> = Vec::new();
+    for note in &data.notes {
+        let mut blocks = code_blocks(&note.body);
+        if blocks.len() != 1 {
+            continue;
+        }
+        let block = blocks.remove(0);
+        let prose = note
+            .body
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .count()
+            .saturating_sub(block.lines + 2);
+        if block.lines <= prose {
+            continue;
+        }
+        let language = block.language.unwrap_or_else(|| "text".to_string());
+        let extension = SNIPPET_EXTENSIONS
+            .iter()
+            .find(|(lang, _)| lang.eq_ignore_ascii_case(&language))
+            .map_or("txt", |(_, ext)| *ext);
+        let stem = match slugify(&note.title) {
+            stem if stem.is_empty() => note.id.clone(),
+            stem => stem,
+        };
+        let filename = unique_file_name(&stem, extension, |f| snippets.iter().any(|(s, _, _)| s == f));
+        snippets.push((filename, language, block.code));
+    }
+    Ok(snippets)
+}
+
+// Ids of notes containing at least one fenced code block
+pub fn notes_with_code(data: &ParcelData) -> Vec<String> {
+    data.notes
+        .iter()
+        .filter(|n| !code_blocks(&n.body).is_empty())
+        .map(|n| n.id.clone())
+        .collect()
+}
+
+// Distinct fence languages per note, for notes with tagged code blocks
+pub fn code_languages(data: &ParcelData) -> HashMap<String, Vec<String>> {
+    let mut languages = HashMap::new();
+    for note in &data.notes {
+        let mut found: Vec<String> = Vec::new();
+        for language in code_blocks(&note.body).into_iter().filter_map(|b| b.language) {
+            if !found.contains(&language) {
+                found.push(language);
+            }
+        }
+        if !found.is_empty() {
+            languages.insert(note.id.clone(), found);
+        }
+    }
+    languages
+}
+
+// Archive live, unpinned notes not updated since `now - older_than_ms`.
+// Returns how many were archived.
+pub fn archive_old_notes(data: &mut ParcelData, now: u64, older_than_ms: u64) -> usize {
+    let cutoff = now.saturating_sub(older_than_ms);
+    let mut archived = 0;
+    for note in data
+        .notes
+        .iter_mut()
+        .filter(|n| !n.archived && !n.pinned && n.updated_at < cutoff)
+    {
+        note.archived = true;
+        archived += 1;
+    }
+    archived
+}
+
+pub fn set_folder_archived(
+    data: &mut ParcelData,
+    folder_id: &str,
+    archived: bool,
+    now: u64,
+) -> anyhow::Result<()> {
+    let folder = data
+        .folders
+        .iter_mut()
+        .find(|f| f.id == folder_id)
+        .ok_or_else(|| anyhow::anyhow!("Folder {} not found", folder_id))?;
+    if folder.archived != archived {
+        folder.archived = archived;
+        folder.updated_at = now;
+    }
+    Ok(())
+}
+
+// Live notes filed in an archived folder, e.g. unarchived after the folder
+// was archived, in data order
+pub fn notes_in_archived_folders(data: &ParcelData) -> Vec<String> {
+    data.notes
+        .iter()
+        .filter(|n| !n.archived)
+        .filter(|n| {
+            data.folders
+                .iter()
+                .any(|f| f.archived && n.folder_id.as_deref() == Some(f.id.as_str()))
+        })
+        .map(|n| n.id.clone())
+        .collect()
+}
+
+// How `fix_notes_in_archived_folders` deals with a live note in an archived folder
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ArchivedFolderFix {
+    // Unarchive the folder the note is in
+    UnarchiveFolder,
+    // Take the note out of the folder
+    MoveOut,
+}
+
+// Apply `fix` to every note `notes_in_archived_folders` reports. Returns how
+// many notes were affected.
+pub fn fix_notes_in_archived_folders(data: &mut ParcelData, fix: ArchivedFolderFix, now: u64) -> usize {
+    let ids = notes_in_archived_folders(data);
+    let touch_on_metadata = data.touch_on_metadata;
+    for note in data.notes.iter_mut().filter(|n| ids.contains(&n.id)) {
+        match fix {
+            ArchivedFolderFix::UnarchiveFolder => {
+                if let Some(folder) = data
+                    .folders
+                    .iter_mut()
+                    .find(|f| note.folder_id.as_deref() == Some(f.id.as_str()))
+                {
+                    folder.archived = false;
+                    folder.updated_at = now;
+                }
+            }
+            ArchivedFolderFix::MoveOut => {
+                note.folder_id = None;
+                touch_metadata(note, touch_on_metadata, now);
+            }
+        }
+    }
+    ids.len()
+}
+
+// Classify every incoming note against the local data without changing anything.
+// Data from a newer version is refused here already, before any decisions.
+pub fn import_plan(current: &ParcelData, incoming: &ParcelData) -> anyhow::Result<ImportPlan> {
+    check_import_version(incoming)?;
+    let entries = incoming
+        .notes
+        .iter()
+        .map(|note| {
+            let status = match current.notes.iter().find(|n| n.id == note.id) {
+                None => ImportStatus::New,
+                Some(local) if local == note => ImportStatus::Identical,
+                Some(_) => ImportStatus::Conflict,
+            };
+            ImportPlanEntry {
+                id: note.id.clone(),
+                title: note.title.clone(),
+                status,
+            }
+        })
+        .collect();
+    Ok(ImportPlan { entries })
+}
+
+// What changed going from `a` to `b`, with ids in the order they appear
+pub fn diff_data(a: &ParcelData, b: &ParcelData) -> anyhow::Result<DataDiff> {
+    // (added, removed, changed) ids between two lists keyed by id
+    fn diff_by_id<T: Serialize>(
+        a: &[T],
+        b: &[T],
+        id: fn(&T) -> &str,
+    ) -> anyhow::Result<(Vec<String>, Vec<String>, Vec<String>)> {
+        let hashes = |items: &[T]| -> anyhow::Result<HashMap<String, String>> {
+            items
+                .iter()
+                .map(|item| Ok((id(item).to_string(), sha256_hex(&serde_json::to_vec(item)?))))
+                .collect()
+        };
+        let (a_hashes, b_hashes) = (hashes(a)?, hashes(b)?);
+        let missing = |items: &[T], other: &HashMap<String, String>| -> Vec<String> {
+            items.iter().map(id).filter(|i| !other.contains_key(*i)).map(String::from).collect()
+        };
+        let (added, removed) = (missing(b, &a_hashes), missing(a, &b_hashes));
+        let changed = b
+            .iter()
+            .map(id)
+            .filter(|i| a_hashes.get(*i).is_some_and(|hash| b_hashes.get(*i) != Some(hash)))
+            .map(String::from)
+            .collect();
+        Ok((added, removed, changed))
+    }
+
+    let (added_notes, removed_notes, changed_notes) = diff_by_id(&a.notes, &b.notes, |n| &n.id)?;
+    let (added_folders, removed_folders, changed_folders) =
+        diff_by_id(&a.folders, &b.folders, |f| &f.id)?;
+    Ok(DataDiff {
+        added_notes,
+        removed_notes,
+        changed_notes,
+        added_folders,
+        removed_folders,
+        changed_folders,
+    })
+}
+
+// `diff_data` on two data files given as JSON text
+pub fn diff_json(a: &str, b: &str) -> anyhow::Result<DataDiff> {
+    let parse = |s: &str, which: &str| -> anyhow::Result<ParcelData> {
+        serde_json::from_str(strip_bom(s).0)
+            .map_err(|e| anyhow::anyhow!("Failed to parse {} file: {}", which, e))
+    };
+    diff_data(&parse(a, "first")?, &parse(b, "second")?)
+}
+
+// Apply the decisions made for an `import_plan`. Only TakeIncoming changes
+// anything: it replaces the local note or adds a new one at the top, bringing
+// along its folder if that folder isn't here yet. Notes without a decision are
+// skipped. Returns how many notes were taken.
+pub fn apply_import_plan(
+    data: &mut ParcelData,
+    incoming: &ParcelData,
+    decisions: &HashMap<String, ImportDecision>,
+    options: &ImportOptions,
+) -> anyhow::Result<usize> {
+    check_import_version(incoming)?;
+    let mut taken = 0;
+    for note in &incoming.notes {
+        if decisions.get(&note.id) != Some(&ImportDecision::TakeIncoming) {
+            continue;
+        }
+        if let Some(folder_id) = &note.folder_id {
+            if !data.folders.iter().any(|f| &f.id == folder_id) {
+                if let Some(folder) = incoming.folders.iter().find(|f| &f.id == folder_id) {
+                    data.folders.push(folder.clone());
+                }
+            }
+        }
+        match data.notes.iter_mut().find(|n| n.id == note.id) {
+            Some(existing) => {
+                let tags = if options.merge_tags {
+                    normalized_tags(existing.tags.iter().chain(&note.tags))
+                } else {
+                    note.tags.clone()
+                };
+                *existing = Note {
+                    tags,
+                    ..note.clone()
+                };
+            }
+            None => data.notes.insert(0, note.clone()),
+        }
+        taken += 1;
+    }
+    Ok(taken)
+}
+
+// Merge every incoming note into the local data: matching ids are replaced,
+// the rest are added
+pub fn import_json(
+    data: &mut ParcelData,
+    incoming: &ParcelData,
+    options: &ImportOptions,
+) -> anyhow::Result<ImportReport> {
+    check_import_version(incoming)?;
+    let mut incoming = incoming.clone();
+    let mut id_map = HashMap::new();
+    if options.regenerate_ids {
+        id_map = regenerate_ids(data, &mut incoming, !options.folder_ids_from_names);
+    }
+    if options.folder_ids_from_names {
+        incoming = with_folder_ids_from_names(&incoming);
+    }
+    let decisions = incoming
+        .notes
+        .iter()
+        .map(|n| (n.id.clone(), ImportDecision::TakeIncoming))
+        .collect();
+    Ok(ImportReport {
+        imported: apply_import_plan(data, &incoming, &decisions, options)?,
+        id_map,
+    })
+}
+
+// Fresh ids for `incoming`'s notes (per `data`'s id strategy) and optionally its
+// folders, with folder references and links by id rewritten. Returns old -> new.
+fn regenerate_ids(
+    data: &mut ParcelData,
+    incoming: &mut ParcelData,
+    folders: bool,
+) -> HashMap<String, String> {
+    let mut id_map: HashMap<String, String> = HashMap::new();
+    for note in &mut incoming.notes {
+        let id = new_id(data);
+        id_map.insert(std::mem::replace(&mut note.id, id.clone()), id);
+    }
+    if folders {
+        for folder in &mut incoming.folders {
+            let id = uuid::Uuid::new_v4().to_string();
+            id_map.insert(std::mem::replace(&mut folder.id, id.clone()), id);
+        }
+    }
+    for note in &mut incoming.notes {
+        if let Some(id) = note.folder_id.as_ref().and_then(|id| id_map.get(id)) {
+            note.folder_id = Some(id.clone());
+        }
+        note.body = rewrite_link_targets(&note.body, &id_map);
+    }
+    id_map
+}
+
+// `body` with every [[target]] or [[target|label]] whose target is a key of
+// `targets` pointed at the mapped value instead
+fn rewrite_link_targets(body: &str, targets: &HashMap<String, String>) -> String {
+    let mut output = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(start) = rest.find("[[") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("]]") else {
+            break;
+        };
+        let link = &after[..end];
+        let (target, label) = match link.split_once('|') {
+            Some((target, label)) => (target, Some(label)),
+            None => (link, None),
+        };
+        output.push_str(&rest[..start + 2]);
+        match targets.get(target.trim()) {
+            Some(new) => {
+                output.push_str(new);
+                if let Some(label) = label {
+                    output.push('|');
+                    output.push_str(label);
+                }
+            }
+            None => output.push_str(link),
+        }
+        output.push_str("]]");
+        rest = &after[end + 2..];
+    }
+    output.push_str(rest);
+    output
+}
+
+// Copy of `data` with folders renamed to their name-derived ids, folders that
+// end up sharing an id collapsed into the first, and notes pointed at them
+fn with_folder_ids_from_names(data: &ParcelData) -> ParcelData {
+    let mut data = data.clone();
+    let ids: HashMap<String, String> = data
+        .folders
+        .iter()
+        .map(|f| (f.id.clone(), folder_id_from_name(&f.name)))
+        .collect();
+    let mut folders: Vec<Folder> = Vec::new();
+    for folder in data.folders.drain(..) {
+        let id = ids[&folder.id].clone();
+        if !folders.iter().any(|f| f.id == id) {
+            folders.push(Folder { id, ..folder });
+        }
+    }
+    data.folders = folders;
+    for note in &mut data.notes {
+        if let Some(id) = note.folder_id.as_ref().and_then(|id| ids.get(id)) {
+            note.folder_id = Some(id.clone());
+        }
+    }
+    data
+}
+
+// Import notes from CSV with a header row containing at least `title` and
+// `body`, plus optional `color` and `folder` (matched by name, created when
+// missing). Invalid colors fall back to paper. Returns how many were imported.
+pub fn import_csv(
+    data: &mut ParcelData,
+    csv: &str,
+    now: u64,
+    mut id_gen: impl FnMut(&mut ParcelData) -> String,
+) -> anyhow::Result<usize> {
+    let mut reader = ::csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(csv.as_bytes());
+    let headers: Vec<String> = reader
+        .headers()?
+        .iter()
+        .map(|h| h.trim().to_lowercase())
+        .collect();
+    let column = |name: &str| headers.iter().position(|h| h == name);
+    let (Some(title_col), Some(body_col)) = (column("title"), column("body")) else {
+        return Err(anyhow::anyhow!("CSV needs title and body columns"));
+    };
+    let color_col = column("color");
+    let folder_col = column("folder");
+
+    // Check every body before anything changes, so one oversize row imports nothing
+    let records = reader.records().collect::<Result<Vec<_>, _>>()?;
+    for (i, record) in records.iter().enumerate() {
+        check_body_len(data, record.get(body_col).unwrap_or(""))
+            .map_err(|e| anyhow::anyhow!("Row {}: {}", i + 1, e))?;
+    }
+
+    let mut imported = Vec::new();
+    for record in records {
+        let field = |col: Option<usize>| col.and_then(|c| record.get(c)).unwrap_or("").trim();
+        let title = field(Some(title_col));
+        let body = record.get(body_col).unwrap_or("");
+        if title.is_empty() && body.trim().is_empty() {
+            continue;
+        }
+        let color = field(color_col).to_lowercase();
+        let color = if NOTE_COLORS.contains(&color.as_str()) {
+            color
+        } else {
+            "paper".to_string()
+        };
+        let folder_name = field(folder_col);
+        let folder_id = (!folder_name.is_empty()).then(|| folder_id_for_name(data, folder_name, now));
+        imported.push(Note {
+            id: id_gen(data),
+            title: title.to_string(),
+            body: body.to_string(),
+            folder_id,
+            color,
+            created_at: now,
+            updated_at: now,
+            ..Default::default()
+        });
+    }
+
+    let count = imported.len();
+    data.notes.splice(0..0, imported);
+    Ok(count)
+}
+
+// Note being read by `import_markdown`
+struct MarkdownNote {
+    id: Option<String>,
+    title: String,
+    folder_name: Option<String>,
+    body: Vec<String>,
+    color: Option<String>,
+    pinned: bool,
+}
+
+// Parse `*Color: x | Pinned: y ...*` metadata lines back into (color, pinned)
+fn parse_markdown_meta(line: &str) -> Option<(String, bool)> {
+    let inner = line.strip_prefix('*')?.strip_suffix('*')?;
+    let mut color = None;
+    let mut pinned = false;
+    for part in inner.split('|') {
+        match part.split_once(':').map(|(k, v)| (k.trim(), v.trim())) {
+            Some(("Color", v)) => color = Some(v.to_string()),
+            Some(("Pinned", v)) => pinned = v == "true",
+            Some(("Words", _)) => {}
+            _ => return None,
+        }
+    }
+    color.map(|c| (c, pinned))
+}
+
+// Import notes from markdown laid out like `export_markdown`: `## Folder:`
+// sections (matched by name, created when missing) holding `### Title` notes.
+// A `<!-- id: ... -->` line under a heading reuses that id, so re-importing an
+// export made with `include_ids` updates those notes in place. Such an export
+// backslash-escapes body lines that look like structure, and the escape is
+// taken off again in notes with an id. Returns how many notes were created or
+// updated.
+pub fn import_markdown(
+    data: &mut ParcelData,
+    markdown: &str,
+    now: u64,
+    mut id_gen: impl FnMut(&mut ParcelData) -> String,
+) -> anyhow::Result<usize> {
+    let mut parsed: Vec<MarkdownNote> = Vec::new();
+    let mut folder_name: Option<String> = None;
+    // Whether the last parsed note can still take body lines
+    let mut open = false;
+    for line in markdown.lines() {
+        let escaped = line.starts_with('\\');
+        if let Some(note) = parsed.last_mut().filter(|n| open && escaped && n.id.is_some()) {
+            note.body.push(unescape_markdown_line(line).to_string());
+        } else if let Some(heading) = line.strip_prefix("## ") {
+            folder_name = heading.strip_prefix("Folder: ").map(|name| name.trim().to_string());
+            open = false;
+        } else if let Some(title) = line.strip_prefix("### ") {
+            parsed.push(MarkdownNote {
+                id: None,
+                title: title.trim().to_string(),
+                folder_name: folder_name.clone(),
+                body: Vec::new(),
+                color: None,
+                pinned: false,
+            });
+            open = true;
+        } else if let Some(note) = parsed.last_mut().filter(|_| open) {
+            let id = line
+                .trim()
+                .strip_prefix("<!-- id:")
+                .and_then(|rest| rest.strip_suffix("-->"))
+                .map(str::trim);
+            let starts_note = note.id.is_none() && note.body.is_empty();
+            if let Some(id) = id.filter(|id| !id.is_empty() && starts_note) {
+                note.id = Some(id.to_string());
+            } else if let Some((color, pinned)) = parse_markdown_meta(line.trim()) {
+                note.color = Some(color);
+                note.pinned = pinned;
+                open = false;
+            } else {
+                note.body.push(line.to_string());
+            }
+        }
+    }
+
+    let bodies: Vec<String> = parsed
+        .iter()
+        .map(|note| {
+            let start = note.body.iter().position(|l| !l.trim().is_empty());
+            let end = note.body.iter().rposition(|l| !l.trim().is_empty());
+            match (start, end) {
+                (Some(start), Some(end)) => note.body[start..=end].join("\n"),
+                _ => String::new(),
+            }
+        })
+        .collect();
+    // Nothing changes, not even folders, unless every body fits
+    for (note, body) in parsed.iter().zip(&bodies) {
+        check_body_len(data, body).map_err(|e| anyhow::anyhow!("Note {:?}: {}", note.title, e))?;
+    }
+
+    let mut imported = 0;
+    for (note, body) in parsed.into_iter().zip(bodies) {
+        let title = if note.title == data.untitled_placeholder {
+            String::new()
+        } else {
+            note.title
+        };
+        let folder_id = note.folder_name.map(|name| folder_id_for_name(data, &name, now));
+        let color = note
+            .color
+            .filter(|c| NOTE_COLORS.contains(&c.as_str()))
+            .unwrap_or_else(|| "paper".to_string());
+
+        match note.id.as_ref().and_then(|id| data.notes.iter_mut().find(|n| &n.id == id)) {
+            Some(existing) => {
+                existing.title = title;
+                existing.body = body;
+                existing.folder_id = folder_id;
+                existing.color = color;
+                if existing.pinned != note.pinned {
+                    existing.pinned = note.pinned;
+                    existing.pinned_at = note.pinned.then_some(now);
+                }
+                existing.updated_at = now;
+            }
+            None => {
+                let id = match note.id {
+                    Some(id) => id,
+                    None => id_gen(data),
+                };
+                data.notes.insert(
+                    0,
+                    Note {
+                        id,
+                        title,
+                        body,
+                        folder_id,
+                        pinned: note.pinned,
+                        color,
+                        created_at: now,
+                        updated_at: now,
+                        pinned_at: note.pinned.then_some(now),
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+        imported += 1;
+    }
+    Ok(imported)
+}
+
+// Id of the folder with this name (ignoring case), creating it if needed
+fn folder_id_for_name(data: &mut ParcelData, name: &str, now: u64) -> String {
+    if let Some(folder) = data
+        .folders
+        .iter()
+        .find(|f| f.name.trim().eq_ignore_ascii_case(name))
+    {
+        return folder.id.clone();
+    }
+    let folder = Folder {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        created_at: now,
+        updated_at: now,
+        archived: false,
+    };
+    let id = folder.id.clone();
+    data.folders.push(folder);
+    id
+}
+
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+// Normalized, de-duplicated tags in first-seen order, without empty ones
+fn normalized_tags<'a>(tags: impl IntoIterator<Item = &'a String>) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = normalize_tag(tag);
+        if !tag.is_empty() && !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    normalized
+}
+
+// Body without trailing spaces/tabs on any line or blank lines at the end.
+// Line endings are kept as they are.
+fn trimmed_body(body: &str) -> String {
+    let mut lines: Vec<String> = body
+        .split('\n')
+        .map(|line| {
+            let (text, cr) = match line.strip_suffix('\r') {
+                Some(text) => (text, "\r"),
+                None => (line, ""),
+            };
+            format!("{}{}", text.trim_end_matches([' ', '\t']), cr)
+        })
+        .collect();
+    while lines.last().is_some_and(|l| l.trim().is_empty()) {
+        lines.pop();
+    }
+    // The final line no longer ends a line
+    if let Some(last) = lines.last_mut() {
+        if last.ends_with('\r') {
+            last.pop();
+        }
+    }
+    lines.join("\n")
+}
+
+// Trim trailing whitespace from every body. Returns how many notes changed.
+pub fn trim_trailing_whitespace(data: &mut ParcelData) -> usize {
+    let mut changed = 0;
+    for note in &mut data.notes {
+        let body = trimmed_body(&note.body);
+        if body != note.body {
+            note.body = body;
+            changed += 1;
+        }
+    }
+    changed
+}
+
+// Lowercase and trim every tag and drop duplicates within each note.
+// Returns how many notes changed.
+pub fn normalize_all_tags(data: &mut ParcelData) -> usize {
+    let mut changed = 0;
+    for note in &mut data.notes {
+        let tags = normalized_tags(&note.tags);
+        if tags != note.tags {
+            note.tags = tags;
+            changed += 1;
+        }
+    }
+    changed
+}
+
+fn has_duplicate_tags(note: &Note) -> bool {
+    note.tags.iter().enumerate().any(|(i, tag)| note.tags[..i].contains(tag))
+}
+
+// Ids of notes listing the exact same tag more than once
+pub fn notes_with_duplicate_tags(data: &ParcelData) -> Vec<String> {
+    data.notes
+        .iter()
+        .filter(|n| has_duplicate_tags(n))
+        .map(|n| n.id.clone())
+        .collect()
+}
+
+// Drop repeated tags, keeping the first of each, without otherwise touching
+// them (see `normalize_all_tags` for that). Returns how many notes changed.
+pub fn dedupe_note_tags(data: &mut ParcelData) -> usize {
+    let mut changed = 0;
+    for note in data.notes.iter_mut().filter(|n| has_duplicate_tags(n)) {
+        let mut tags: Vec<String> = Vec::new();
+        for tag in note.tags.drain(..) {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        note.tags = tags;
+        changed += 1;
+    }
+    changed
+}
+
+// Targets of [[wiki links]] in a body, in order. `[[target|label]]` links to `target`.
+pub fn wiki_links(body: &str) -> Vec<String> {
+    let mut links = Vec::new();
+    let mut rest = body;
+    while let Some(start) = rest.find("[[") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("]]") else {
+            break;
+        };
+        let target = after[..end].split('|').next().unwrap_or("").trim();
+        if !target.is_empty() {
+            links.push(target.to_string());
+        }
+        rest = &after[end + 2..];
+    }
+    links
+}
+
+// Distinct http(s) URLs in a body, in order. Catches bare URLs, <autolinks> and
+// markdown link targets; trailing sentence punctuation isn't part of the URL.
+fn external_urls(body: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    let mut rest = body;
+    while let Some(start) = ["http://", "https://"].iter().filter_map(|p| rest.find(p)).min() {
+        let candidate = &rest[start..];
+        let end = candidate
+            .find(|c: char| c.is_whitespace() || "<>()[]\"'`".contains(c))
+            .unwrap_or(candidate.len());
+        let url = candidate[..end].trim_end_matches(['.', ',', ';', ':', '!', '?']);
+        let has_host = url.split_once("://").is_some_and(|(_, host)| !host.is_empty());
+        if has_host && !urls.iter().any(|u| u == url) {
+            urls.push(url.to_string());
+        }
+        rest = &candidate[end.max(1)..];
+    }
+    urls
+}
+
+// Each note with external URLs, alongside its distinct URLs
+pub fn notes_with_urls(data: &ParcelData) -> Vec<(String, Vec<String>)> {
+    data.notes
+        .iter()
+        .map(|n| (n.id.clone(), external_urls(&n.body)))
+        .filter(|(_, urls)| !urls.is_empty())
+        .collect()
+}
+
+// The note a link target refers to: an exact id, else a title ignoring case
+pub fn resolve_link<'a>(data: &'a ParcelData, target: &str) -> Option<&'a Note> {
+    data.notes.iter().find(|n| n.id == target).or_else(|| {
+        data.notes
+            .iter()
+            .find(|n| !n.title.is_empty() && n.title.trim().eq_ignore_ascii_case(target))
+    })
+}
+
+// Ids of the other notes `note`'s [[links]] resolve to, each once
+fn linked_ids<'a>(data: &'a ParcelData, note: &Note) -> Vec<&'a str> {
+    let mut ids: Vec<&str> = Vec::new();
+    for target in wiki_links(&note.body) {
+        if let Some(linked) = resolve_link(data, &target) {
+            if linked.id != note.id && !ids.contains(&linked.id.as_str()) {
+                ids.push(&linked.id);
+            }
+        }
+    }
+    ids
+}
+
+// Ids of the other notes with a [[link]] resolving to `note_id`, in data order
+pub fn find_backlinks(data: &ParcelData, note_id: &str) -> Vec<String> {
+    data.notes
+        .iter()
+        .filter(|n| linked_ids(data, n).contains(&note_id))
+        .map(|n| n.id.clone())
+        .collect()
+}
+
+// Every note with its backlink count, most linked first, then by id. Each body
+// is parsed once, rather than once per note as `find_backlinks` would.
+pub fn notes_by_backlink_count(data: &ParcelData) -> Vec<(String, usize)> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for note in &data.notes {
+        for id in linked_ids(data, note) {
+            *counts.entry(id).or_default() += 1;
+        }
+    }
+    let mut ranked: Vec<(String, usize)> = data
+        .notes
+        .iter()
+        .map(|n| (n.id.clone(), counts.get(n.id.as_str()).copied().unwrap_or(0)))
+        .collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ranked
+}
+
+// Markdown export of a note and every note reachable from it through at most
+// `max_depth` hops of [[links]], breadth first, each note once
+pub fn export_note_closure_markdown(
+    data: &ParcelData,
+    note_id: &str,
+    max_depth: usize,
+) -> anyhow::Result<String> {
+    use std::fmt::Write;
+
+    let start = data
+        .notes
+        .iter()
+        .find(|n| n.id == note_id)
+        .ok_or_else(|| anyhow::anyhow!("Note {} not found", note_id))?;
+    let mut reached: Vec<&Note> = vec![start];
+    let mut frontier: Vec<&Note> = vec![start];
+    for _ in 0..max_depth {
+        let mut next: Vec<&Note> = Vec::new();
+        for note in frontier {
+            for target in wiki_links(&note.body) {
+                if let Some(linked) = resolve_link(data, &target) {
+                    if !reached.iter().any(|n| n.id == linked.id) {
+                        reached.push(linked);
+                        next.push(linked);
+                    }
+                }
+            }
+        }
+        if next.is_empty() {
+            break;
+        }
+        frontier = next;
+    }
+
+    let mut output = String::new();
+    writeln!(output, "# {} and linked notes\n", display_title(start, &data.untitled_placeholder))?;
+    write_markdown_notes(&mut output, &reached, data, &ExportOptions::default())?;
+    Ok(output)
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// Graphviz digraph of notes and the [[links]] between them. Links to unknown
+// notes point at dashed "missing" nodes.
+pub fn export_graph_dot(data: &ParcelData) -> anyhow::Result<String> {
+    use std::fmt::Write;
+
+    let mut output = String::new();
+    writeln!(output, "digraph parcel {{")?;
+    for note in &data.notes {
+        writeln!(
+            output,
+            "  \"{}\" [label=\"{}\"];",
+            dot_escape(&note.id),
+            dot_escape(display_title(note, &data.untitled_placeholder))
+        )?;
+    }
+
+    let mut missing: Vec<String> = Vec::new();
+    let mut edges: Vec<(String, String, bool)> = Vec::new();
+    for note in &data.notes {
+        for target in wiki_links(&note.body) {
+            let edge = match resolve_link(data, &target) {
+                Some(linked) => (note.id.clone(), linked.id.clone(), false),
+                None => {
+                    let node = format!("missing:{}", target);
+                    if !missing.contains(&target) {
+                        missing.push(target);
+                    }
+                    (note.id.clone(), node, true)
+                }
+            };
+            if !edges.contains(&edge) {
+                edges.push(edge);
+            }
+        }
+    }
+
+    for target in &missing {
+        writeln!(
+            output,
+            "  \"{}\" [label=\"{}\", style=dashed];",
+            dot_escape(&format!("missing:{}", target)),
+            dot_escape(target)
+        )?;
+    }
+    for (from, to, dashed) in edges {
+        let style = if dashed { " [style=dashed]" } else { "" };
+        writeln!(output, "  \"{}\" -> \"{}\"{};", dot_escape(&from), dot_escape(&to), style)?;
+    }
+    writeln!(output, "}}")?;
+    Ok(output)
+}
+
+// Order notes the way the note list does: pinned first (most recently pinned
+// on top), then by `order`. Untitled notes sort as `placeholder`.
+pub fn sort_notes(notes: &mut [&Note], order: SortOrder, placeholder: &str) {
+    notes.sort_by(|a, b| {
+        let pin_order = b.pinned.cmp(&a.pinned).then_with(|| {
+            if a.pinned && b.pinned {
+                b.pinned_at.cmp(&a.pinned_at)
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        });
+        pin_order.then_with(|| match order {
+            SortOrder::Updated => b.updated_at.cmp(&a.updated_at),
+            SortOrder::Created => b.created_at.cmp(&a.created_at),
+            SortOrder::Title => display_title(a, placeholder)
+                .to_lowercase()
+                .cmp(&display_title(b, placeholder).to_lowercase()),
+        })
+    });
+}
+
+// Ids of notes with a body but no title, most recently updated first
+pub fn titleless_notes(data: &ParcelData) -> Vec<String> {
+    let mut notes: Vec<&Note> = data
+        .notes
+        .iter()
+        .filter(|n| n.title.trim().is_empty() && !n.body.trim().is_empty())
+        .collect();
+    notes.sort_by_key(|n| std::cmp::Reverse(n.updated_at));
+    notes.into_iter().map(|n| n.id.clone()).collect()
+}
+
+// Ids of notes last updated before their folder was created, which only
+// happens when imports mix data from different places
+pub fn notes_predating_folder(data: &ParcelData) -> Vec<String> {
+    data.notes
+        .iter()
+        .filter(|n| {
+            let folder = n.folder_id.as_deref().and_then(|id| data.folders.iter().find(|f| f.id == id));
+            folder.is_some_and(|f| n.updated_at < f.created_at)
+        })
+        .map(|n| n.id.clone())
+        .collect()
+}
+
+// Ids of notes sharing the exact same body (ignoring surrounding whitespace),
+// in groups of two or more ordered by first appearance. Empty bodies don't count.
+pub fn identical_body_groups(data: &ParcelData) -> Vec<Vec<String>> {
+    use std::collections::hash_map::Entry;
+
+    let mut groups: Vec<Vec<String>> = Vec::new();
+    let mut by_hash: HashMap<String, usize> = HashMap::new();
+    for note in &data.notes {
+        let body = note.body.trim();
+        if body.is_empty() {
+            continue;
+        }
+        match by_hash.entry(sha256_hex(body.as_bytes())) {
+            Entry::Occupied(group) => groups[*group.get()].push(note.id.clone()),
+            Entry::Vacant(slot) => {
+                slot.insert(groups.len());
+                groups.push(vec![note.id.clone()]);
+            }
+        }
+    }
+    groups.retain(|ids| ids.len() >= 2);
+    groups
+}
+
+// Ids of notes whose title just repeats the body's first line, ignoring case
+pub fn redundant_titles(data: &ParcelData) -> Vec<String> {
+    data.notes
+        .iter()
+        .filter(|n| {
+            let title = n.title.trim();
+            !title.is_empty()
+                && first_line(&n.body).is_some_and(|line| line.to_lowercase() == title.to_lowercase())
+        })
+        .map(|n| n.id.clone())
+        .collect()
+}
+
+// Folder names that collide once trimmed and lowercased, with the ids sharing each,
+// sorted by name; ids keep folder order
+pub fn duplicate_folder_names(data: &ParcelData) -> Vec<(String, Vec<String>)> {
+    let mut by_name: HashMap<String, Vec<String>> = HashMap::new();
+    for folder in &data.folders {
+        by_name
+            .entry(folder.name.trim().to_lowercase())
+            .or_default()
+            .push(folder.id.clone());
+    }
+    let mut duplicates: Vec<(String, Vec<String>)> =
+        by_name.into_iter().filter(|(_, ids)| ids.len() > 1).collect();
+    duplicates.sort_by(|a, b| a.0.cmp(&b.0));
+    duplicates
+}
+
+// (id, preview) where the preview is the body on one line, cut to `len`
+// characters with a trailing ellipsis when anything was cut
+pub fn note_previews(data: &ParcelData, len: usize) -> Vec<(String, String)> {
+    data.notes
+        .iter()
+        .map(|note| {
+            let flat = note
+                .body
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ");
+            let mut preview: String = flat.chars().take(len).collect();
+            if preview.len() < flat.len() {
+                preview.push('…');
+            }
+            (note.id.clone(), preview)
+        })
+        .collect()
+}
+
+// Notes of both folders, pinned first then most recently updated. Read-only.
+pub fn folder_notes_union(
+    data: &ParcelData,
+    folder_a: &str,
+    folder_b: &str,
+) -> anyhow::Result<Vec<Note>> {
+    for folder_id in [folder_a, folder_b] {
+        if !data.folders.iter().any(|f| f.id == folder_id) {
+            return Err(anyhow::anyhow!("Folder {} not found", folder_id));
+        }
+    }
+    let mut notes: Vec<&Note> = Vec::new();
+    for note in data
+        .notes
+        .iter()
+        .filter(|n| matches!(n.folder_id.as_deref(), Some(id) if id == folder_a || id == folder_b))
+    {
+        if !notes.iter().any(|n| n.id == note.id) {
+            notes.push(note);
+        }
+    }
+    sort_notes(&mut notes, SortOrder::Updated, &data.untitled_placeholder);
+    Ok(notes.into_iter().cloned().collect())
+}
+
+// Notes whose title contains `query` (case-insensitive), pinned first then most
+// recently updated. Bodies are ignored; an empty query matches everything.
+pub fn search_titles(data: &ParcelData, query: &str) -> Vec<Note> {
+    let query = query.to_lowercase();
+    let mut matches: Vec<&Note> = data
+        .notes
+        .iter()
+        .filter(|n| n.title.to_lowercase().contains(&query))
+        .collect();
+    sort_notes(&mut matches, SortOrder::Updated, &data.untitled_placeholder);
+    matches.into_iter().cloned().collect()
+}
+
+// Ids of notes with at least one attachment, most recently updated first
+pub fn notes_with_attachments(data: &ParcelData) -> Vec<String> {
+    let mut notes: Vec<&Note> = data.notes.iter().filter(|n| !n.attachments.is_empty()).collect();
+    notes.sort_by_key(|n| std::cmp::Reverse(n.updated_at));
+    notes.into_iter().map(|n| n.id.clone()).collect()
+}
+
+// Add `tag` (normalized) to every note the note list search would show for
+// `query`: a case-insensitive substring of the title or body. An empty query
+// would match everything, so it's refused. Returns how many notes gained the tag.
+pub fn tag_search_results(
+    data: &mut ParcelData,
+    query: &str,
+    tag: &str,
+    now: u64,
+) -> anyhow::Result<usize> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return Err(anyhow::anyhow!("Search query is empty; refusing to tag every note"));
+    }
+    let tag = normalize_tag(tag);
+    if tag.is_empty() {
+        return Err(anyhow::anyhow!("Tag is empty"));
+    }
+    let touch_on_metadata = data.touch_on_metadata;
+    let mut tagged = 0;
+    for note in data.notes.iter_mut().filter(|n| {
+        n.title.to_lowercase().contains(&query) || n.body.to_lowercase().contains(&query)
+    }) {
+        if !note.tags.iter().any(|t| normalize_tag(t) == tag) {
+            note.tags.push(tag.clone());
+            touch_metadata(note, touch_on_metadata, now);
+            tagged += 1;
+        }
+    }
+    Ok(tagged)
+}
+
+// Weight of a title match relative to the same match in the body
+const TITLE_MATCH_WEIGHT: f32 = 3.0;
+
+// Notes matching any word of `query`, best first. Each occurrence of a word scores
+// one point, or two when it stands as a whole word, and title points count
+// TITLE_MATCH_WEIGHT times. Case-insensitive; ties keep data order.
+pub fn search_ranked(data: &ParcelData, query: &str) -> Vec<(Note, f32)> {
+    fn score(text: &str, term: &str) -> f32 {
+        let partial = text.matches(term).count();
+        let whole = text.split(|c: char| !c.is_alphanumeric()).filter(|w| *w == term).count();
+        (partial + whole) as f32
+    }
+
+    let terms: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+    if terms.is_empty() {
+        return Vec::new();
+    }
+    let mut ranked: Vec<(Note, f32)> = data
+        .notes
+        .iter()
+        .filter_map(|note| {
+            let (title, body) = (note.title.to_lowercase(), note.body.to_lowercase());
+            let total: f32 = terms
+                .iter()
+                .map(|t| TITLE_MATCH_WEIGHT * score(&title, t) + score(&body, t))
+                .sum();
+            (total > 0.0).then(|| (note.clone(), total))
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked
+}
+
+// 1-based position of a note within its folder under `order`, and the folder's note count
+pub fn note_position(
+    data: &ParcelData,
+    note_id: &str,
+    order: SortOrder,
+) -> anyhow::Result<(usize, usize)> {
+    let note = data
+        .notes
+        .iter()
+        .find(|n| n.id == note_id)
+        .ok_or_else(|| anyhow::anyhow!("Note {} not found", note_id))?;
+    let mut siblings: Vec<&Note> = data
+        .notes
+        .iter()
+        .filter(|n| n.folder_id == note.folder_id)
+        .collect();
+    sort_notes(&mut siblings, order, &data.untitled_placeholder);
+    let index = siblings.iter().position(|n| n.id == note_id).unwrap_or(0);
+    Ok((index + 1, siblings.len()))
+}
+
+// One continuous markdown document from a folder's notes, e.g. chapters of a
+// manuscript: the folder name as the title, then each note's title as a heading
+// followed by its body, in note-list title order. No per-note metadata.
+pub fn export_folder_merged_markdown(data: &ParcelData, folder_id: &str) -> anyhow::Result<String> {
+    use std::fmt::Write;
+
+    let folder = data
+        .folders
+        .iter()
+        .find(|f| f.id == folder_id)
+        .ok_or_else(|| anyhow::anyhow!("Folder {} not found", folder_id))?;
+    let mut notes: Vec<&Note> = data
+        .notes
+        .iter()
+        .filter(|n| n.folder_id.as_deref() == Some(folder_id))
+        .collect();
+    sort_notes(&mut notes, SortOrder::Title, &data.untitled_placeholder);
+
+    let mut output = String::new();
+    writeln!(output, "# {}\n", folder.name)?;
+    for note in notes {
+        writeln!(output, "## {}\n", display_title(note, &data.untitled_placeholder))?;
+        if !note.body.trim().is_empty() {
+            writeln!(output, "{}\n", note.body.trim_end())?;
+        }
+    }
+    Ok(output)
+}
+
+// Flat markdown list of the `count` most recently updated notes, newest first
+pub fn export_recent_markdown(data: &ParcelData, count: usize) -> anyhow::Result<String> {
+    use std::fmt::Write;
+
+    let mut notes: Vec<&Note> = data.notes.iter().collect();
+    notes.sort_by_key(|n| std::cmp::Reverse(n.updated_at));
+    notes.truncate(count);
+
+    let mut output = String::new();
+    writeln!(output, "# Recently edited\n")?;
+    write_markdown_notes(&mut output, &notes, data, &ExportOptions::default())?;
+    Ok(output)
+}
+
+fn format_day(ms: u64) -> String {
+    utc_day(ms)
+        .map(|d| d.format("%Y-%m-%d").to_string())
+        .unwrap_or_else(|| ms.to_string())
+}
+
+// Markdown export of notes whose created or updated time (per `by`) falls in
+// the inclusive range [from, to], grouped by folder
+pub fn export_range_markdown(
+    data: &ParcelData,
+    from: u64,
+    to: u64,
+    by: &str,
+) -> anyhow::Result<String> {
+    use std::fmt::Write;
+
+    let timestamp: fn(&Note) -> u64 = match by {
+        "created" => |n| n.created_at,
+        "updated" => |n| n.updated_at,
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Invalid date field: {}. Expected created or updated.",
+                by
+            ))
+        }
+    };
+    let mut in_range = data.clone();
+    in_range.notes.retain(|n| (from..=to).contains(&timestamp(n)));
+
+    let mut output = String::new();
+    writeln!(
+        output,
+        "# Notes {} {} to {}\n",
+        by,
+        format_day(from),
+        format_day(to)
+    )?;
+    writeln!(output, "*Total notes: {}*\n", in_range.notes.len())?;
+    write_note_groups(&mut output, &in_range, &ExportOptions::default(), false)?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(id: &str, title: &str, body: &str, folder_id: Option<&str>, at: u64) -> Note {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "title": title,
+            "body": body,
+            "folderId": folder_id,
+            "pinned": false,
+            "color": "paper",
+            "createdAt": at,
+            "updatedAt": at,
+        }))
+        .unwrap()
+    }
+
+    fn data(notes: Vec<Note>, folders: Vec<Folder>) -> ParcelData {
+        ParcelData { notes, folders, ..ParcelData::empty() }
+    }
+
+    fn folder(id: &str, name: &str, at: u64) -> Folder {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": name,
+            "createdAt": at,
+            "updatedAt": at,
+        }))
+        .unwrap()
+    }
+
+    // A fresh app data directory under the system temp dir, unique per test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("parcel-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn ids(notes: &[Note]) -> Vec<&str> {
+        notes.iter().map(|n| n.id.as_str()).collect()
+    }
+
+    #[test]
+    fn stale_notes_boundary_and_order() {
+        let d = data(
+            vec![
+                note("a", "", "", None, 50),
+                note("b", "", "", None, 10),
+                note("c", "", "", None, 100),
+            ],
+            vec![],
+        );
+        // Exactly `older_than_ms` old is not stale yet
+        assert_eq!(ids(&stale_notes(&d, 150, 100)), vec!["b"]);
+        assert_eq!(ids(&stale_notes(&d, 150, 60)), vec!["b", "a"]);
+    }
+
+    #[test]
+    fn toggle_note_folder_both_ways() {
+        let mut d = data(
+            vec![note("n", "", "", Some("a"), 1)],
+            vec![folder("a", "A", 1), folder("b", "B", 1)],
+        );
+        assert_eq!(toggle_note_folder(&mut d, "n", "a", "b", 1).unwrap(), "b");
+        assert_eq!(toggle_note_folder(&mut d, "n", "a", "b", 1).unwrap(), "a");
+        assert!(toggle_note_folder(&mut d, "n", "a", "zz", 1).is_err());
+        assert_eq!(d.notes[0].folder_id.as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn counter_ids_are_sequential() {
+        let mut d = data(
+            vec![note("note-0003", "", "", None, 1)],
+            vec![folder("f", "F", 1)],
+        );
+        set_id_strategy(&mut d, "counter").unwrap();
+        let a = create_note(&mut d, Some("f".into()), "mint", 5).unwrap();
+        let b = create_note(&mut d, None, "paper", 5).unwrap();
+        assert_eq!((a.id.as_str(), b.id.as_str()), ("note-0004", "note-0005"));
+        assert!(set_id_strategy(&mut d, "nope").is_err());
+        assert!(create_note(&mut d, Some("x".into()), "paper", 5).is_err());
+        set_id_strategy(&mut d, "uuid").unwrap();
+        assert_eq!(new_id(&mut d).len(), 36);
+        let s = serde_json::to_string(&d).unwrap();
+        assert!(
+            s.contains("\"idStrategy\":\"uuid\"") && s.contains("\"nextCounter\":6"),
+            "{s}"
+        );
+    }
+
+    #[test]
+    fn heatmap_aggregates_by_day() {
+        // 2024-03-05T10:00Z
+        let t = 1_709_632_800_000;
+        let d = data(
+            vec![
+                note("a", "", "", None, t),
+                note("b", "", "", None, t + 3_600_000),
+                note("c", "", "", None, t + DAY_MS * 400),
+            ],
+            vec![],
+        );
+        let h = activity_heatmap(&d, 2024);
+        assert_eq!(h.len(), 366);
+        assert!(h.contains(&("2024-03-05".to_string(), 2)));
+        assert!(h.contains(&("2024-03-06".to_string(), 0)));
+        assert_eq!(h.iter().map(|x| x.1).sum::<usize>(), 2);
+    }
+
+    #[test]
+    fn new_notes_use_default_folder() {
+        let mut d = data(vec![], vec![folder("f", "F", 1), folder("g", "G", 1)]);
+        set_default_folder(&mut d, Some("f".into())).unwrap();
+        assert!(set_default_folder(&mut d, Some("zz".into())).is_err());
+        let n = create_note(&mut d, None, "paper", 1).unwrap();
+        assert_eq!(n.folder_id.as_deref(), Some("f"));
+        let n = create_note(&mut d, Some("g".into()), "paper", 1).unwrap();
+        assert_eq!(n.folder_id.as_deref(), Some("g"));
+        // Deleting the default folder clears it on the next load
+        d.folders.remove(0);
+        let dir = temp_dir("default-folder");
+        save(dir.clone(), &mut d).unwrap();
+        assert_eq!(load(dir).unwrap().default_folder_id, None);
+    }
+
+    #[test]
+    fn folder_stats_aggregate() {
+        let mut n2 = note("b", "two words", "and three more", Some("f"), 30);
+        n2.pinned = true;
+        let d = data(
+            vec![
+                note("a", "", "one two", Some("f"), 10),
+                n2,
+                note("c", "x", "", None, 5),
+            ],
+            vec![folder("f", "F", 1), folder("g", "G", 1)],
+        );
+        let s = folder_stats(&d);
+        assert_eq!(s.len(), 3);
+        assert_eq!(
+            (
+                s[0].note_count,
+                s[0].word_count,
+                s[0].pinned_count,
+                s[0].last_updated
+            ),
+            (2, 5, 1, Some(30))
+        );
+        assert_eq!(s[1].last_updated, None);
+        assert_eq!((s[2].folder_id.clone(), s[2].note_count), (None, 1));
+    }
+
+    #[test]
+    fn body_lines_range() {
+        let d = data(vec![note("a", "", "l0\nl1\nl2\nl3\nl4", None, 1)], vec![]);
+        assert_eq!(note_body_lines(&d, "a", 2, 2).unwrap(), vec!["l2", "l3"]);
+        assert!(note_body_lines(&d, "a", 9, 2).unwrap().is_empty());
+        assert!(note_body_lines(&d, "z", 0, 2).is_err());
+    }
+
+    #[test]
+    fn bulk_replace_across_notes() {
+        let mut d = data(
+            vec![
+                note("a", "", "old proj here", None, 1),
+                note("b", "", "proj x", None, 1),
+                note("c", "", "nothing", None, 1),
+            ],
+            vec![],
+        );
+        assert_eq!(
+            bulk_replace(&mut d, r"\bproj\b", "project", None, 9).unwrap(),
+            2
+        );
+        assert_eq!(d.notes[0].body, "old project here");
+        assert_eq!(d.notes[0].updated_at, 9);
+        assert_eq!(d.notes[2].updated_at, 1);
+        assert_eq!(
+            bulk_replace(&mut d, "project", "P", Some(vec!["b".into()]), 9).unwrap(),
+            1
+        );
+        assert!(bulk_replace(&mut d, "(", "", None, 9)
+            .unwrap_err()
+            .to_string()
+            .contains("Invalid pattern"));
+    }
+
+    #[test]
+    fn backups_keep_newest() {
+        let dir = temp_dir("backup");
+        assert!(backup(dir.clone(), 1).unwrap().is_none());
+        save(dir.clone(), &mut data(vec![], vec![])).unwrap();
+        for t in 0..12u64 {
+            backup(dir.clone(), 1_000_000_000_000 + t).unwrap();
+        }
+        let kept = fs::read_dir(dir.join("parcel/backups")).unwrap().count();
+        assert_eq!(kept, MAX_BACKUPS);
+        assert!(dir.join("parcel/backups/notes-1000000000011.json").exists());
+        assert!(!dir.join("parcel/backups/notes-1000000000000.json").exists());
+    }
+
+    #[test]
+    fn preview_replace_leaves_data() {
+        let d = data(
+            vec![
+                note("a", "", "x x x", None, 1),
+                note("b", "", "y", None, 1),
+                note("c", "", "x", None, 1),
+            ],
+            vec![],
+        );
+        let before = serde_json::to_string(&d).unwrap();
+        assert_eq!(
+            preview_bulk_replace(&d, "x", None).unwrap(),
+            vec![("a".to_string(), 3), ("c".to_string(), 1)]
+        );
+        assert_eq!(
+            preview_bulk_replace(&d, "x", Some(vec!["c".into()]))
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(preview_bulk_replace(&d, "[", None).is_err());
+        assert_eq!(before, serde_json::to_string(&d).unwrap());
+    }
+
+    #[test]
+    fn docx_contains_document_xml() {
+        use std::io::Read;
+
+        let d = data(
+            vec![
+                note("a", "Hello", "line one\nline two", Some("f"), 1),
+                note("b", "", "loose", None, 1),
+            ],
+            vec![folder("f", "Work", 1)],
+        );
+        let bytes = export_docx(&d).unwrap();
+        let mut z = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+        let mut xml = String::new();
+        z.by_name("word/document.xml")
+            .unwrap()
+            .read_to_string(&mut xml)
+            .unwrap();
+        assert!(
+            xml.contains("Heading1")
+                && xml.contains("Work")
+                && xml.contains("line two")
+                && xml.contains("Untitled")
+        );
+    }
+
+    #[test]
+    fn import_plaintext_title() {
+        let mut d = data(vec![], vec![folder("f", "F", 1)]);
+        let n =
+            import_plaintext(&mut d, "\n  First line \nmore", None, None, 3, "x".into()).unwrap();
+        assert_eq!(n.title, "First line");
+        let n = import_plaintext(
+            &mut d,
+            "First\nmore",
+            Some("Mine".into()),
+            Some("f".into()),
+            3,
+            "y".into(),
+        )
+        .unwrap();
+        assert_eq!(n.title, "Mine");
+        assert!(import_plaintext(&mut d, "a", None, Some("q".into()), 3, "z".into()).is_err());
+        assert_eq!(d.notes.len(), 2);
+    }
+
+    #[test]
+    fn folders_sorted_by_activity() {
+        let d = data(
+            vec![
+                note("a", "", "", Some("old"), 5),
+                note("b", "", "", Some("new"), 50),
+            ],
+            vec![
+                folder("old", "O", 1),
+                folder("new", "N", 1),
+                folder("empty", "E", 20),
+            ],
+        );
+        let f: Vec<String> = folders_by_activity(&d).into_iter().map(|f| f.id).collect();
+        assert_eq!(f, vec!["new", "empty", "old"]);
+    }
+
+    #[test]
+    fn export_at_version_range() {
+        let d = data(vec![note("a", "t", "b", None, 1)], vec![]);
+        let s = export_json_at_version(&d, 1).unwrap();
+        assert!(s.contains("\"version\": 1"));
+        assert!(export_json_at_version(&d, 0).is_err());
+        assert!(export_json_at_version(&d, CURRENT_VERSION + 1).is_err());
+    }
+
+    #[test]
+    fn v1_export_omits_tags() {
+        let mut a = note("a", "Alpha", "", None, 1);
+        a.tags = vec!["work".into()];
+        let d = data(vec![a], vec![]);
+        assert!(!export_json_at_version(&d, 1).unwrap().contains("tags"));
+        assert!(export_json_at_version(&d, 2).unwrap().contains("tags"));
+    }
+
+    #[test]
+    fn reading_time_minutes() {
+        let n = note("a", "", &"w ".repeat(400), None, 1);
+        assert_eq!(reading_time(&n, 200), 2);
+        assert_eq!(reading_time(&note("b", "", "", None, 1), 200), 1);
+        assert_eq!(reading_time(&note("b", "", "a b c", None, 1), 2), 2);
+    }
+
+    #[test]
+    fn markdown_header_and_footer() {
+        let d = data(vec![note("a", "T", "body", None, 1)], vec![]);
+        let plain = export_markdown(&d, &ExportOptions::default()).unwrap();
+        assert!(plain.starts_with("# Parcel Notes Export"));
+        let o = ExportOptions {
+            header: Some("HEAD".into()),
+            footer: Some("<b>FOOT</b>".into()),
+            ..Default::default()
+        };
+        let s = export_markdown(&d, &o).unwrap();
+        assert!(s.starts_with("HEAD\n\n# Parcel"));
+        assert!(s.trim_end().ends_with("<b>FOOT</b>"));
+        assert!(s.contains("### T\n\nbody\n\n*Color: paper | Pinned: false | Words: 1*"));
+    }
+
+    #[test]
+    fn validate_note_issues() {
+        let mut n = note("a", "T", "b", None, 1);
+        assert!(validate_note(&n).is_empty());
+        n.color = "neon".into();
+        assert_eq!(validate_note(&n).len(), 1);
+        n.title = " T ".into();
+        n.id = String::new();
+        assert_eq!(validate_note(&n).len(), 3);
+    }
+
+    #[test]
+    fn split_layout_writes_changed_notes_only() {
+        let dir = temp_dir("split");
+        let mut d = data(
+            vec![
+                note("b", "B", "b", None, 1),
+                note("a", "A", "a", Some("f"), 1),
+            ],
+            vec![folder("f", "F", 1)],
+        );
+        save(dir.clone(), &mut d).unwrap();
+        convert_storage_layout(dir.clone(), "split", 1).unwrap();
+        assert!(!dir.join("parcel/notes.json").exists());
+        let loaded = load(dir.clone()).unwrap();
+        assert_eq!(loaded.storage_layout, "split");
+        assert_eq!(
+            loaded
+                .notes
+                .iter()
+                .map(|n| n.id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["b", "a"]
+        );
+        // edit one note; only its file changes
+        let a_path = dir.join("parcel/notes/a.json");
+        let b_path = dir.join("parcel/notes/b.json");
+        let b_mtime = fs::metadata(&b_path).unwrap().modified().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let mut edited = loaded.clone();
+        edited.notes[1].body = "changed".into();
+        save(dir.clone(), &mut edited).unwrap();
+        assert_eq!(fs::metadata(&b_path).unwrap().modified().unwrap(), b_mtime);
+        assert!(fs::read_to_string(&a_path).unwrap().contains("changed"));
+        // deleting a note removes its file
+        edited.notes.remove(0);
+        save(dir.clone(), &mut edited).unwrap();
+        assert!(!b_path.exists());
+        convert_storage_layout(dir.clone(), "single", 2).unwrap();
+        assert!(!dir.join("parcel/folders.json").exists() && !dir.join("parcel/notes").exists());
+        assert_eq!(load(dir.clone()).unwrap().notes.len(), 1);
+        assert!(convert_storage_layout(dir.clone(), "zip", 3).is_err());
+        assert_eq!(fs::read_dir(dir.join("parcel/backups")).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn layout_conversion_round_trip() {
+        let dir = temp_dir("roundtrip");
+        let mut d = data(
+            vec![
+                note("b", "B", "b", None, 1),
+                note("a", "A", "a", Some("f"), 1),
+            ],
+            vec![folder("f", "F", 1)],
+        );
+        save(dir.clone(), &mut d).unwrap();
+        let before = load(dir.clone()).unwrap();
+        convert_storage_layout(dir.clone(), "split", 1).unwrap();
+        convert_storage_layout(dir.clone(), "single", 2).unwrap();
+        let after = load(dir.clone()).unwrap();
+        assert_eq!(after.save_seq, before.save_seq + 2);
+        assert_eq!(
+            ParcelData {
+                save_seq: before.save_seq,
+                ..after
+            },
+            before
+        );
+    }
+
+    #[test]
+    fn wal_replayed_then_truncated() {
+        use std::io::Write;
+
+        let dir = temp_dir("wal");
+        save(
+            dir.clone(),
+            &mut data(vec![note("a", "A", "old", None, 1)], vec![]),
+        )
+        .unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let mut a = note("a", "A", "new", None, 2);
+        append_wal(dir.clone(), &a).unwrap();
+        a.body = "newer".into();
+        append_wal(dir.clone(), &a).unwrap();
+        append_wal(dir.clone(), &note("b", "B", "", None, 3)).unwrap();
+        // A write torn by a crash ends the replay
+        fs::OpenOptions::new()
+            .append(true)
+            .open(dir.join("parcel/wal.log"))
+            .unwrap()
+            .write_all(b"{\"id\":\"c\",\"ti")
+            .unwrap();
+        assert_eq!(replay_wal(dir.clone()).unwrap(), 3);
+        assert_eq!(fs::metadata(dir.join("parcel/wal.log")).unwrap().len(), 0);
+        let d = load(dir.clone()).unwrap();
+        assert_eq!(d.notes.len(), 2);
+        assert_eq!(d.notes.iter().find(|n| n.id == "a").unwrap().body, "newer");
+        assert_eq!(replay_wal(dir).unwrap(), 0);
+    }
+
+    #[test]
+    fn load_with_wal_only_reads() {
+        let dir = temp_dir("wal-read");
+        save(
+            dir.clone(),
+            &mut data(vec![note("a", "A", "old", None, 1)], vec![]),
+        )
+        .unwrap();
+        let before = fs::read_to_string(dir.join("parcel/notes.json")).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        append_wal(dir.clone(), &note("a", "A", "new", None, 2)).unwrap();
+        let (d, _) = load_with_wal(dir.clone()).unwrap();
+        assert_eq!(d.notes[0].body, "new");
+        assert_eq!(d.save_seq, 1);
+        assert_eq!(
+            fs::read_to_string(dir.join("parcel/notes.json")).unwrap(),
+            before
+        );
+        assert_eq!(pending_changes(dir).unwrap(), vec!["a"]);
+    }
+
+    #[test]
+    fn pending_edits_until_flush() {
+        let dir = temp_dir("pending");
+        let seed = vec![
+            note("a", "A", "", None, 1),
+            note("b", "B", "", None, 1),
+            note("c", "C", "", None, 1),
+        ];
+        save(dir.clone(), &mut data(seed, vec![])).unwrap();
+        assert!(pending_changes(dir.clone()).unwrap().is_empty());
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        // The way the edit commands journal instead of saving
+        for (id, body) in [("a", "1"), ("b", "1"), ("a", "2")] {
+            let (mut d, _) = load_with_wal(dir.clone()).unwrap();
+            let edited = update_note(&mut d, id, None, Some(body.into()), 2).unwrap();
+            append_wal(dir.clone(), &edited).unwrap();
+        }
+        assert_eq!(pending_changes(dir.clone()).unwrap(), vec!["a", "b"]);
+        assert_eq!(replay_wal(dir.clone()).unwrap(), 3);
+        assert!(pending_changes(dir.clone()).unwrap().is_empty());
+        let d = load(dir).unwrap();
+        let bodies: Vec<&str> = d.notes.iter().map(|n| n.body.as_str()).collect();
+        assert_eq!(bodies, vec!["2", "1", ""]);
+    }
+
+    #[test]
+    fn body_limit_rejects_without_mutating() {
+        let mut d = data(vec![note("a", "A", "12345", None, 1)], vec![]);
+        d.max_body_len = Some(8);
+        let e = append_to_note(&mut d, "a", "abc", 5).unwrap_err();
+        assert!(e.downcast_ref::<ParcelError>().is_some());
+        assert_eq!(d.notes[0].body, "12345");
+        assert_eq!(d.notes[0].updated_at, 1);
+        assert_eq!(
+            append_to_note(&mut d, "a", "ab", 5).unwrap().body,
+            "12345\nab"
+        );
+        d.max_body_len = None;
+        assert!(update_note(&mut d, "a", None, Some("x".repeat(100)), 6).is_ok());
+        d.max_body_len = Some(3);
+        assert!(import_plaintext(&mut d, "long text", None, None, 1, "q".into()).is_err());
+    }
+
+    #[test]
+    fn outline_entry_depths() {
+        let d = data(
+            vec![note("a", "A", "", Some("f"), 1), note("b", "", "", None, 1)],
+            vec![folder("f", "F", 1), folder("g", "G", 1)],
+        );
+        let o: Vec<(String, usize)> = outline(&d).into_iter().map(|e| (e.id, e.depth)).collect();
+        assert_eq!(
+            o,
+            vec![
+                ("f".into(), 0),
+                ("a".into(), 1),
+                ("g".into(), 0),
+                ("b".into(), 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn tags_index_lists_overlapping_notes() {
+        let mut a = note("a", "Alpha", "", None, 1);
+        a.tags = vec!["work".into(), "home".into()];
+        let mut b = note("b", "Beta", "", None, 1);
+        b.tags = vec!["work".into()];
+        let c = note("c", "Gamma", "", None, 1);
+        let s = export_tags_index(&data(vec![a, b, c], vec![])).unwrap();
+        assert_eq!(
+            s,
+            "# Tags\n\n## home\n\n- [[Alpha]]\n\n## work\n\n- [[Alpha]]\n- [[Beta]]\n\n"
+        );
+    }
+
+    #[test]
+    fn save_stamps_current_version() {
+        let dir = temp_dir("save-version");
+        let mut a = note("a", "A", "", None, 1);
+        a.tags = vec!["work".into()];
+        // What the frontend sends: a v1 label on data with v2 fields
+        let mut d = data(vec![a], vec![]);
+        d.version = 1;
+        save(dir.clone(), &mut d).unwrap();
+        let raw: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(dir.join("parcel/notes.json")).unwrap())
+                .unwrap();
+        assert_eq!(raw["version"], CURRENT_VERSION);
+        assert_eq!(raw["notes"][0]["tags"][0], "work");
+
+        d.pinned_save_version = Some(1);
+        save(dir.clone(), &mut d).unwrap();
+        let raw: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(dir.join("parcel/notes.json")).unwrap())
+                .unwrap();
+        assert_eq!(raw["version"], 1);
+        assert!(raw["notes"][0].get("tags").is_none());
+    }
+
+    #[test]
+    fn note_extremes_by_words() {
+        let d = data(
+            vec![
+                note("a", "", "one two", None, 1),
+                note("b", "", "one two three four", None, 1),
+                note("c", "", "one", None, 1),
+                note("d", "", "uno", None, 5),
+            ],
+            vec![],
+        );
+        assert_eq!(note_extremes(&d), (Some("b".into()), Some("d".into())));
+        assert_eq!(note_extremes(&data(vec![], vec![])), (None, None));
+    }
+
+    #[test]
+    fn protected_delete_needs_force() {
+        let mut d = data(
+            vec![note("a", "", "", None, 1), note("b", "", "", None, 1)],
+            vec![],
+        );
+        set_protected(&mut d, "a", true).unwrap();
+        let r = delete_notes(&mut d, &["a".into(), "b".into()], false);
+        assert_eq!((r.deleted, r.skipped), (1, 1));
+        assert_eq!(d.notes.len(), 1);
+        let r = delete_notes(&mut d, &["a".into()], true);
+        assert_eq!((r.deleted, r.skipped), (1, 0));
+        assert!(d.notes.is_empty());
+    }
+
+    #[test]
+    fn saving_without_protected_note_keeps_it() {
+        let mut stored = data(
+            vec![
+                note("a", "", "", None, 1),
+                note("b", "", "", None, 1),
+                note("c", "", "", None, 1),
+            ],
+            vec![],
+        );
+        set_protected(&mut stored, "b", true).unwrap();
+        // A frontend payload that dropped both b and c
+        let mut payload = data(vec![note("a", "", "edited", None, 2)], vec![]);
+        assert_eq!(keep_protected_notes(&mut payload, &stored), 1);
+        assert_eq!(ids(&payload.notes), vec!["a", "b"]);
+        assert_eq!(payload.notes[0].body, "edited");
+        assert_eq!(keep_protected_notes(&mut payload, &stored), 0);
+    }
+
+    #[test]
+    fn code_fence_notes_found() {
+        let d = data(
+            vec![
+                note("a", "", "text\n```rust\nfn x() {}\n```\n", None, 1),
+                note("b", "", "use `inline` ticks", None, 1),
+                note("c", "", "```\nunclosed", None, 1),
+            ],
+            vec![],
+        );
+        assert_eq!(notes_with_code(&d), vec!["a"]);
+    }
+
+    #[test]
+    fn code_languages_per_note() {
+        let d = data(
+            vec![
+                note(
+                    "a",
+                    "",
+                    "```rust\nx\n```\n```rust\ny\n```\n```py\nz\n```",
+                    None,
+                    1,
+                ),
+                note("b", "", "```\nq\n```", None, 1),
+            ],
+            vec![],
+        );
+        let l = code_languages(&d);
+        assert_eq!(
+            l.get("a").unwrap(),
+            &vec!["rust".to_string(), "py".to_string()]
+        );
+        assert!(!l.contains_key("b"));
+    }
+
+    #[test]
+    fn markdown_word_count_footer() {
+        let d = data(vec![note("a", "T", "one two three", None, 1)], vec![]);
+        assert!(export_markdown(&d, &ExportOptions::default())
+            .unwrap()
+            .contains("*Color: paper | Pinned: false | Words: 3*"));
+        let o: ExportOptions = serde_json::from_str("{\"wordCount\": false}").unwrap();
+        assert!(export_markdown(&d, &o)
+            .unwrap()
+            .contains("*Color: paper | Pinned: false*\n"));
+        let o: ExportOptions = serde_json::from_str("{}").unwrap();
+        assert!(o.word_count);
+    }
+
+    #[test]
+    fn archive_skips_pinned() {
+        let mut p = note("p", "", "", None, 1);
+        p.pinned = true;
+        let mut d = data(
+            vec![note("a", "", "", None, 1), p, note("n", "", "", None, 100)],
+            vec![],
+        );
+        assert_eq!(archive_old_notes(&mut d, 100, 50), 1);
+        assert!(d.notes[0].archived && !d.notes[1].archived && !d.notes[2].archived);
+        assert_eq!(archive_old_notes(&mut d, 100, 50), 0);
+        assert_eq!(stale_notes(&d, 100, 50).len(), 1);
+    }
+
+    #[test]
+    fn csv_import_quoted_fields() {
+        let mut d = data(
+            vec![note("x", "", "", None, 1)],
+            vec![folder("w", "Work", 1)],
+        );
+        let csv = "Title,Body,Color,Folder\n\
+                   First,\"hello, world\",mint,work\n\
+                   Second,\"multi\nline \"\"q\"\"\",neon,Home\n";
+        assert_eq!(import_csv(&mut d, csv, 5, new_id).unwrap(), 2);
+        assert_eq!(d.notes[0].body, "hello, world");
+        assert_eq!(d.notes[0].folder_id.as_deref(), Some("w"));
+        assert_eq!(d.notes[0].color, "mint");
+        assert_eq!(d.notes[1].body, "multi\nline \"q\"");
+        assert_eq!(d.notes[1].color, "paper");
+        assert_eq!(d.folders.len(), 2);
+        assert_eq!(d.notes[2].id, "x");
+        assert!(import_csv(&mut d, "name\nx", 5, new_id).is_err());
+    }
+
+    #[test]
+    fn imports_respect_body_limit() {
+        let mut d = data(vec![], vec![]);
+        d.max_body_len = Some(5);
+        let csv = "Title,Body,Folder\nOk,short,Work\nBig,way too long,Work\n";
+        let e = import_csv(&mut d, csv, 1, new_id).unwrap_err();
+        assert!(e.to_string().starts_with("Row 2:"), "{e}");
+        let md = "## Folder: Work\n\n### Ok\n\nshort\n\n### Big\n\nway too long\n";
+        assert!(import_markdown(&mut d, md, 1, new_id).is_err());
+        // Neither import left anything behind, not even the folder
+        assert!(d.notes.is_empty() && d.folders.is_empty());
+        d.max_body_len = None;
+        assert_eq!(import_csv(&mut d, csv, 1, new_id).unwrap(), 2);
+    }
+
+    #[test]
+    fn normalize_tags_merges_case() {
+        let mut a = note("a", "", "", None, 1);
+        a.tags = vec!["Work".into(), " work ".into(), "".into()];
+        let mut b = note("b", "", "", None, 1);
+        b.tags = vec!["ok".into()];
+        let mut d = data(vec![a, b], vec![]);
+        assert_eq!(normalize_all_tags(&mut d), 1);
+        assert_eq!(d.notes[0].tags, vec!["work"]);
+        assert_eq!(normalize_all_tags(&mut d), 0);
+    }
+
+    #[test]
+    fn dot_graph_edges() {
+        let d = data(
+            vec![
+                note(
+                    "a",
+                    "Say \"hi\"",
+                    "see [[beta]] and [[Beta|again]] and [[ghost]]",
+                    None,
+                    1,
+                ),
+                note("b", "Beta", "", None, 1),
+            ],
+            vec![],
+        );
+        let s = export_graph_dot(&d).unwrap();
+        assert!(s.starts_with("digraph parcel {\n") && s.ends_with("}\n"));
+        assert!(s.contains("\"a\" [label=\"Say \\\"hi\\\"\"];"));
+        assert_eq!(s.matches("\"a\" -> \"b\";").count(), 1);
+        assert!(s.contains("\"a\" -> \"missing:ghost\" [style=dashed];"));
+        assert_eq!(s.matches('{').count(), s.matches('}').count());
+    }
+
+    #[test]
+    fn note_position_in_folder() {
+        let mut p = note("p", "", "", Some("f"), 1);
+        p.pinned = true;
+        let d = data(
+            vec![
+                note("a", "", "", Some("f"), 50),
+                p,
+                note("b", "", "", Some("f"), 20),
+                note("z", "", "", None, 99),
+            ],
+            vec![folder("f", "F", 1)],
+        );
+        assert_eq!(note_position(&d, "p", SortOrder::Updated).unwrap(), (1, 3));
+        assert_eq!(note_position(&d, "b", SortOrder::Updated).unwrap(), (3, 3));
+        assert_eq!(note_position(&d, "z", SortOrder::Title).unwrap(), (1, 1));
+        assert!(note_position(&d, "q", SortOrder::Created).is_err());
+        assert_eq!(
+            serde_json::from_str::<SortOrder>("\"updated\"").unwrap(),
+            SortOrder::Updated
+        );
+    }
+
+    #[test]
+    fn backups_respect_interval() {
+        let dir = temp_dir("interval");
+        save(dir.clone(), &mut data(vec![], vec![])).unwrap();
+        let hour = 3_600_000;
+        assert!(backup_if_due(dir.clone(), 1_000_000, hour)
+            .unwrap()
+            .is_some());
+        assert!(backup_if_due(dir.clone(), 1_000_000 + 10, hour)
+            .unwrap()
+            .is_none());
+        assert_eq!(fs::read_dir(dir.join("parcel/backups")).unwrap().count(), 1);
+        assert!(backup_if_due(dir.clone(), 1_000_000 + hour, hour)
+            .unwrap()
+            .is_some());
+        assert!(backup(dir.clone(), 1_000_000 + hour + 1).unwrap().is_some());
+    }
+
+    #[test]
+    fn date_range_export() {
+        let mut a = note("a", "InRange", "", Some("f"), 100);
+        a.updated_at = 500;
+        let b = note("b", "Outside", "", None, 300);
+        let d = data(vec![a, b], vec![folder("f", "F", 1), folder("g", "G", 1)]);
+        let s = export_range_markdown(&d, 50, 200, "created").unwrap();
+        assert!(s.contains("InRange") && !s.contains("Outside") && !s.contains("Folder: G"));
+        let s = export_range_markdown(&d, 250, 300, "updated").unwrap();
+        assert!(!s.contains("InRange") && s.contains("Outside"));
+        assert!(s.starts_with("# Notes updated 1970-01-01 to 1970-01-01"));
+        assert!(export_range_markdown(&d, 0, 1, "deleted").is_err());
+        // full export still lists empty folders
+        assert!(export_markdown(&d, &ExportOptions::default())
+            .unwrap()
+            .contains("## Folder: G"));
+    }
+
+    #[test]
+    fn pinned_at_orders_pins() {
+        let mut d = data(
+            vec![
+                note("a", "A", "", None, 10),
+                note("b", "B", "", None, 20),
+                note("c", "C", "", None, 30),
+            ],
+            vec![],
+        );
+        set_pinned(&mut d, "a", true, 100).unwrap();
+        set_pinned(&mut d, "b", true, 200).unwrap();
+        let mut v: Vec<&Note> = d.notes.iter().collect();
+        sort_notes(&mut v, SortOrder::Updated, DEFAULT_UNTITLED);
+        let ids: Vec<&str> = v.iter().map(|n| n.id.as_str()).collect();
+        assert_eq!(ids, ["b", "a", "c"]);
+        set_pinned(&mut d, "b", false, 300).unwrap();
+        assert_eq!(d.notes[1].pinned_at, None);
+        // migration
+        let mut m = data(vec![note("x", "X", "", None, 7)], vec![]);
+        m.notes[0].pinned = true;
+        let dir = temp_dir("pinmig");
+        save(dir.clone(), &mut m).unwrap();
+        let m = load(dir).unwrap();
+        assert_eq!(m.notes[0].pinned_at, Some(7));
+    }
+
+    #[test]
+    fn create_notes_from_titles() {
+        let mut d = data(
+            vec![note("old", "Old", "", None, 1)],
+            vec![folder("f", "F", 1)],
+        );
+        let titles: Vec<String> = ["One", " ", "Two", "Three"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let mut n = 0;
+        let made = create_notes_batch(&mut d, &titles, Some("f".into()), "mint", 5, |_| {
+            n += 1;
+            format!("id{n}")
+        })
+        .unwrap();
+        assert_eq!(made.len(), 3);
+        assert!(made
+            .iter()
+            .all(|m| m.folder_id.as_deref() == Some("f") && m.body.is_empty()));
+        let order: Vec<&str> = d.notes.iter().map(|n| n.title.as_str()).collect();
+        assert_eq!(order, ["One", "Two", "Three", "Old"]);
+        assert!(
+            create_notes_batch(&mut d, &titles, Some("nope".into()), "mint", 5, new_id).is_err()
+        );
+        assert!(create_notes_batch(&mut d, &titles, None, "red", 5, new_id).is_err());
+    }
+
+    #[test]
+    fn kanban_groups_by_color() {
+        let mut a = note("a", "Todo", "", None, 1);
+        a.color = "yellow".into();
+        let b = note("b", "", "", None, 1);
+        let mut c = note("c", "Done", "", None, 1);
+        c.color = "sky".into();
+        let s = export_kanban_markdown(&data(vec![c, a, b], vec![])).unwrap();
+        assert_eq!(s, "# Board\n\n## paper\n\n- [ ] Untitled\n\n## yellow\n\n- [ ] Todo\n\n## sky\n\n- [ ] Done\n\n");
+    }
+
+    #[test]
+    fn folder_name_suggestions() {
+        let d = data(
+            vec![],
+            vec![
+                folder("1", "Work", 1),
+                folder("2", "home", 1),
+                folder("3", "Workouts", 1),
+                folder("4", "Work", 1),
+                folder("5", "wiki", 1),
+            ],
+        );
+        assert_eq!(folder_names(&d, "WO"), ["Work", "Workouts"]);
+        assert_eq!(folder_names(&d, ""), ["home", "wiki", "Work", "Workouts"]);
+        assert!(folder_names(&d, "zzz").is_empty());
+    }
+
+    #[test]
+    fn bom_is_stripped_and_reported() {
+        let dir = temp_dir("bom");
+        save(
+            dir.clone(),
+            &mut data(vec![note("a", "A", "", None, 1)], vec![]),
+        )
+        .unwrap();
+        let path = dir.join("parcel/notes.json");
+        let s = fs::read_to_string(&path).unwrap();
+        assert!(!load_with_diagnostics(dir.clone()).unwrap().1.bom_stripped);
+        fs::write(&path, format!("\u{feff}{s}")).unwrap();
+        let (d, diag) = load_with_diagnostics(dir.clone()).unwrap();
+        assert!(diag.bom_stripped);
+        assert_eq!(d.notes[0].id, "a");
+        assert!(load(dir).is_ok());
+    }
+
+    #[test]
+    fn save_seq_counts_saves() {
+        let dir = temp_dir("seq");
+        let mut d = data(vec![], vec![]);
+        assert_eq!(load(dir.clone()).unwrap().save_seq, 0);
+        save(dir.clone(), &mut d).unwrap();
+        let mut loaded = load(dir.clone()).unwrap();
+        save(dir.clone(), &mut loaded).unwrap();
+        assert_eq!(loaded.save_seq, 2);
+        assert_eq!(load(dir).unwrap().save_seq, 2);
+    }
+
+    #[test]
+    fn frontend_save_with_pending_wal_bumps_once() {
+        let dir = temp_dir("seq-wal");
+        save(
+            dir.clone(),
+            &mut data(vec![note("a", "A", "", None, 1)], vec![]),
+        )
+        .unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        append_wal(dir.clone(), &note("a", "A", "logged", None, 2)).unwrap();
+        // What save_notes does with the payload the frontend sends
+        let mut payload = data(vec![note("a", "A", "typed", None, 3)], vec![]);
+        let (stored, _) = load_with_wal(dir.clone()).unwrap();
+        carry_over_settings(&mut payload, &stored);
+        save(dir.clone(), &mut payload).unwrap();
+        clear_wal(dir.clone()).unwrap();
+        let loaded = load(dir.clone()).unwrap();
+        assert_eq!(loaded.save_seq, 2);
+        assert_eq!(loaded.notes[0].body, "typed");
+        assert!(pending_changes(dir).unwrap().is_empty());
+    }
+
+    #[test]
+    fn snippets_use_fence_language() {
+        let a = note(
+            "a",
+            "Hello World!",
+            "```rust\nfn main() {\n    println!(\"hi\");\n}\n```\n",
+            None,
+            1,
+        );
+        let b = note(
+            "b",
+            "Prose",
+            "lots\nof\ntext\nhere\n```sh\nls\n```",
+            None,
+            1,
+        );
+        let c = note("c", "", "```\nplain\n```", None, 1);
+        let d = note("d", "Hello world", "Intro\n```rust\nx\ny\n```", None, 1);
+        let s = export_snippets(&data(vec![a, b, c, d], vec![])).unwrap();
+        assert_eq!(
+            s[0],
+            (
+                "hello-world.rs".to_string(),
+                "rust".to_string(),
+                "fn main() {\n    println!(\"hi\");\n}\n".to_string()
+            )
+        );
+        assert_eq!(
+            s[1],
+            (
+                "c.txt".to_string(),
+                "text".to_string(),
+                "plain\n".to_string()
+            )
+        );
+        assert_eq!(s[2].0, "hello-world-2.rs");
+        assert_eq!(s.len(), 3);
+    }
+
+    #[test]
+    fn title_search_ignores_bodies() {
+        let mut p = note("p", "Old Recipe", "", None, 1);
+        p.pinned = true;
+        let d = data(
+            vec![
+                note("a", "recipes", "", None, 5),
+                note("b", "Other", "recipe in body", None, 9),
+                p,
+            ],
+            vec![],
+        );
+        let ids: Vec<String> = search_titles(&d, "RECIPE")
+            .into_iter()
+            .map(|n| n.id)
+            .collect();
+        assert_eq!(ids, ["p", "a"]);
+        assert_eq!(search_titles(&d, "").len(), 3);
+    }
+
+    #[test]
+    fn untitled_placeholder_in_exports() {
+        let mut d = data(vec![note("a", "", "body", None, 1), note("b", "Ob", "", None, 1)], vec![]);
+        assert!(export_markdown(&d, &ExportOptions::default())
+            .unwrap()
+            .contains("### Untitled"));
+        assert_eq!(note_position(&d, "a", SortOrder::Title).unwrap(), (2, 2));
+        set_untitled_placeholder(&mut d, "No Title").unwrap();
+        assert_eq!(note_position(&d, "a", SortOrder::Title).unwrap(), (1, 2));
+        let md = export_markdown(&d, &ExportOptions::default()).unwrap();
+        assert!(md.contains("### No Title") && !md.contains("Untitled"));
+        assert!(export_kanban_markdown(&d)
+            .unwrap()
+            .contains("- [ ] No Title"));
+        assert!(set_untitled_placeholder(&mut d, "  ").is_err());
+    }
+
+    #[test]
+    fn attachments_listed_newest_first() {
+        let mut a = note("a", "A", "", None, 5);
+        a.attachments = vec!["scan.pdf".into()];
+        let mut c = note("c", "C", "", None, 9);
+        c.attachments = vec!["1.png".into(), "2.png".into()];
+        let d = data(vec![a, note("b", "B", "", None, 7), c], vec![]);
+        assert_eq!(notes_with_attachments(&d), ["c", "a"]);
+        let old: Note = serde_json::from_str(r#"{"id":"x","title":"","body":"","folderId":null,"pinned":false,"color":"paper","createdAt":1,"updatedAt":1}"#).unwrap();
+        assert!(old.attachments.is_empty());
+    }
+
+    #[test]
+    fn folder_json_files_reimport() {
+        let d = data(
+            vec![
+                note("a", "A", "", Some("f"), 1),
+                note("b", "B", "", None, 1),
+                note("c", "C", "", Some("g"), 1),
+            ],
+            vec![
+                folder("f", "Work Stuff", 1),
+                folder("g", "work stuff", 1),
+                folder("h", "Empty", 1),
+            ],
+        );
+        let root = temp_dir("jsontree").join("out");
+        assert_eq!(export_folders_json_tree(&d, &root, false).unwrap(), 4);
+        let mut names: Vec<String> = fs::read_dir(&root)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "_folderless.json",
+                "empty.json",
+                "work-stuff-2.json",
+                "work-stuff.json"
+            ]
+        );
+        for name in names {
+            let dir = temp_dir(&format!("jsontree-{name}"));
+            fs::create_dir_all(dir.join("parcel")).unwrap();
+            fs::copy(root.join(&name), dir.join("parcel/notes.json")).unwrap();
+            let loaded = load(dir).unwrap();
+            if name == "work-stuff.json" {
+                assert_eq!(loaded.notes[0].id, "a");
+                assert_eq!(loaded.folders[0].id, "f");
+            }
+        }
+    }
+
+    #[test]
+    fn import_plan_decisions() {
+        let mut cur = data(
+            vec![
+                note("same", "S", "x", None, 1),
+                note("c", "Local", "mine", None, 1),
+            ],
+            vec![],
+        );
+        let inc = data(
+            vec![
+                note("same", "S", "x", None, 1),
+                note("c", "Remote", "theirs", Some("f"), 2),
+                note("n", "New", "", None, 3),
+                note("m", "Skipped", "", None, 3),
+            ],
+            vec![folder("f", "F", 1)],
+        );
+        let plan = import_plan(&cur, &inc).unwrap();
+        let st: Vec<ImportStatus> = plan.entries.iter().map(|e| e.status).collect();
+        assert_eq!(
+            st,
+            [
+                ImportStatus::Identical,
+                ImportStatus::Conflict,
+                ImportStatus::New,
+                ImportStatus::New
+            ]
+        );
+        let mut dec = std::collections::HashMap::new();
+        dec.insert("c".to_string(), ImportDecision::TakeIncoming);
+        dec.insert("n".to_string(), ImportDecision::KeepLocal);
+        assert_eq!(
+            apply_import_plan(&mut cur, &inc, &dec, &Default::default()).unwrap(),
+            1
+        );
+        assert_eq!(cur.notes.len(), 2);
+        assert_eq!(cur.notes[1].body, "theirs");
+        assert_eq!(cur.folders[0].id, "f");
+        let mut cur2 = cur.clone();
+        dec.insert("c".to_string(), ImportDecision::KeepLocal);
+        dec.insert("n".to_string(), ImportDecision::TakeIncoming);
+        assert_eq!(
+            apply_import_plan(&mut cur2, &inc, &dec, &Default::default()).unwrap(),
+            1
+        );
+        assert_eq!(cur2.notes[0].id, "n");
+    }
+
+    #[test]
+    fn folder_default_tags_on_create() {
+        let mut d = data(vec![], vec![folder("f", "F", 1), folder("g", "G", 1)]);
+        set_folder_default_tags(&mut d, "f", &["Work".to_string(), "work".to_string()]).unwrap();
+        assert!(set_folder_default_tags(&mut d, "zz", &[]).is_err());
+        let n = create_note(&mut d, Some("f".into()), "paper", 1).unwrap();
+        assert_eq!(n.tags, ["work"]);
+        assert!(create_note(&mut d, Some("g".into()), "paper", 1)
+            .unwrap()
+            .tags
+            .is_empty());
+        let made = create_notes_batch(
+            &mut d,
+            &["a".to_string()],
+            Some("f".into()),
+            "paper",
+            1,
+            new_id,
+        )
+        .unwrap();
+        assert_eq!(made[0].tags, ["work"]);
+    }
+
+    #[test]
+    fn previews_truncate_on_chars() {
+        let d = data(
+            vec![
+                note("a", "", "héllo\n\nwörld", None, 1),
+                note("b", "", "short", None, 1),
+            ],
+            vec![],
+        );
+        let p = note_previews(&d, 7);
+        assert_eq!(p[0], ("a".to_string(), "héllo w…".to_string()));
+        assert_eq!(p[1].1, "short");
+        assert_eq!(note_previews(&d, 11)[0].1, "héllo wörld");
+    }
+
+    #[test]
+    fn clean_backups_removes_strays() {
+        let dir = temp_dir("cleanbk");
+        assert_eq!(clean_backups(dir.clone()).unwrap(), 0);
+        save(dir.clone(), &mut data(vec![], vec![])).unwrap();
+        backup(dir.clone(), 5).unwrap();
+        let bk = dir.join("parcel/backups");
+        fs::write(bk.join("readme.txt"), "x").unwrap();
+        fs::write(bk.join("notes-12a.json"), "x").unwrap();
+        fs::create_dir(bk.join("sub")).unwrap();
+        assert_eq!(clean_backups(dir.clone()).unwrap(), 2);
+        assert!(bk.join("notes-5.json").exists() && bk.join("sub").exists());
+        assert!(!bk.join("readme.txt").exists());
+    }
+
+    #[test]
+    fn folder_union_dedupes() {
+        let mut p = note("p", "P", "", Some("b"), 1);
+        p.pinned = true;
+        let d = data(
+            vec![
+                note("x", "X", "", Some("a"), 5),
+                note("x", "X dup", "", Some("b"), 6),
+                note("y", "Y", "", Some("b"), 9),
+                note("z", "Z", "", Some("c"), 9),
+                p,
+            ],
+            vec![
+                folder("a", "A", 1),
+                folder("b", "B", 1),
+                folder("c", "C", 1),
+            ],
+        );
+        let ids: Vec<String> = folder_notes_union(&d, "a", "b")
+            .unwrap()
+            .into_iter()
+            .map(|n| n.id)
+            .collect();
+        assert_eq!(ids, ["p", "y", "x"]);
+        assert!(folder_notes_union(&d, "a", "nope").is_err());
+    }
+
+    #[test]
+    fn separator_between_notes() {
+        let d = data(
+            vec![
+                note("a", "A", "", Some("f"), 1),
+                note("b", "B", "", Some("f"), 1),
+                note("c", "C", "", Some("g"), 1),
+            ],
+            vec![folder("f", "F", 1), folder("g", "G", 1)],
+        );
+        let opts = ExportOptions {
+            separator: Some("---".into()),
+            ..Default::default()
+        };
+        let md = export_markdown(&d, &opts).unwrap();
+        assert_eq!(md.matches("---").count(), 1);
+        let a = md.find("### A").unwrap();
+        let sep = md.find("---").unwrap();
+        let b = md.find("### B").unwrap();
+        assert!(a < sep && sep < b);
+        assert!(!export_markdown(&d, &ExportOptions::default())
+            .unwrap()
+            .contains("---"));
+    }
+
+    #[test]
+    fn edit_streak_counts_days() {
+        let day = DAY_MS;
+        // two-day run, gap, then three consecutive days (10, 11, 12)
+        let d = data(
+            vec![
+                note("a", "", "", None, day),
+                note("b", "", "", None, 2 * day + 5),
+                note("c", "", "", None, 10 * day),
+                note("d", "", "", None, 11 * day + 100),
+                note("e", "", "", None, 12 * day),
+                note("f", "", "", None, 12 * day + 9),
+            ],
+            vec![],
+        );
+        let s = edit_streak(&d, 12 * day + 3600);
+        assert_eq!((s.current_streak, s.longest_streak), (3, 3));
+        assert_eq!(s.last_edit_day.as_deref(), Some("1970-01-13"));
+        assert_eq!(edit_streak(&d, 13 * day).current_streak, 3);
+        assert_eq!(edit_streak(&d, 14 * day).current_streak, 0);
+        assert_eq!(edit_streak(&data(vec![], vec![]), 0).longest_streak, 0);
+    }
+
+    #[test]
+    fn export_dispatches_by_format() {
+        let mut d = data(
+            vec![
+                note("a", "A & B", "line1\nline2\n\n<p2>", Some("f"), 1),
+                note("b", "", "x", Some("f"), 1),
+                note("c", "C", "", None, 1),
+            ],
+            vec![folder("f", "Work", 1)],
+        );
+        d.notes[0].tags = vec!["t1".into(), "t2".into()];
+        let opts = ExportOptions {
+            header: Some("HEAD".into()),
+            footer: Some("FOOT".into()),
+            word_count: false,
+            separator: Some("***".into()),
+            ..Default::default()
+        };
+        let text = |f| match export(&d, f, &opts).unwrap() {
+            ExportOutput::Text(t) => t,
+            _ => panic!(),
+        };
+        let md = text(ExportFormat::Markdown);
+        assert!(md.starts_with("HEAD") && md.contains("***") && !md.contains("Words:"));
+        let html = text(ExportFormat::Html);
+        assert!(
+            html.contains("<h3>A &amp; B</h3>")
+                && html.contains("<p>line1<br>\nline2</p>")
+                && html.contains("&lt;p2&gt;")
+        );
+        assert!(
+            html.contains("HEAD")
+                && html.contains("FOOT")
+                && html.contains("<hr>")
+                && !html.contains("Words:")
+        );
+        assert_eq!(text(ExportFormat::Json), export_json(&d).unwrap());
+        let csv = text(ExportFormat::Csv);
+        assert!(
+            csv.starts_with("id,title,body,color,folder")
+                && csv.contains("t1;t2")
+                && csv.contains(",Work,")
+        );
+        let mut back = data(vec![], vec![]);
+        assert_eq!(import_csv(&mut back, &csv, 1, new_id).unwrap(), 3);
+        assert_eq!(back.notes[0].body, "line1\nline2\n\n<p2>");
+        let plain = text(ExportFormat::Plaintext);
+        assert!(
+            plain.starts_with("HEAD")
+                && plain.contains("Work\n====")
+                && plain.contains("Untitled\n--------")
+                && plain.contains("***")
+        );
+        let opml = text(ExportFormat::Opml);
+        assert!(
+            opml.contains("<outline text=\"Work\">") && opml.contains("_note=\"line1&#10;line2")
+        );
+        assert!(
+            matches!(export(&d, ExportFormat::Docx, &opts).unwrap(), ExportOutput::Bytes(b) if b.starts_with(b"PK"))
+        );
+        let f: ExportFormat = serde_json::from_str("\"plaintext\"").unwrap();
+        assert_eq!(f, ExportFormat::Plaintext);
+        assert_eq!(
+            serde_json::to_value(ExportOutput::Text("x".into())).unwrap(),
+            serde_json::json!({"kind":"text","content":"x"})
+        );
+    }
+
+    #[test]
+    fn archived_folder_notes_found_and_fixed() {
+        let mut gone = folder("gone", "Gone", 1);
+        gone.archived = true;
+        let mut archived_note = note("b", "B", "", Some("gone"), 1);
+        archived_note.archived = true;
+        let mut d = data(
+            vec![
+                note("a", "A", "", Some("gone"), 1),
+                archived_note,
+                note("c", "C", "", Some("live"), 1),
+            ],
+            vec![gone, folder("live", "Live", 1)],
+        );
+        assert_eq!(notes_in_archived_folders(&d), ["a"]);
+        let mut moved = d.clone();
+        assert_eq!(
+            fix_notes_in_archived_folders(&mut moved, ArchivedFolderFix::MoveOut, 50),
+            1
+        );
+        assert_eq!(
+            (
+                moved.notes[0].folder_id.as_deref(),
+                moved.notes[0].updated_at
+            ),
+            (None, 50)
+        );
+        assert!(moved.folders[0].archived);
+        assert_eq!(
+            fix_notes_in_archived_folders(&mut d, ArchivedFolderFix::UnarchiveFolder, 50),
+            1
+        );
+        assert!(!d.folders[0].archived && notes_in_archived_folders(&d).is_empty());
+        let old: Folder =
+            serde_json::from_str(r#"{"id":"f","name":"F","createdAt":1,"updatedAt":1}"#).unwrap();
+        assert!(!old.archived);
+    }
+
+    #[test]
+    fn bundle_rejects_tampering() {
+        use std::io::{Read, Write};
+        let d = data(vec![note("a", "A", "hello", None, 1)], vec![]);
+        let bytes = export_bundle(&d).unwrap();
+        let back = read_bundle(&bytes).unwrap();
+        assert_eq!(back.notes, d.notes);
+        // flip a byte inside the stored data
+        for pos in [40, bytes.len() / 3, bytes.len() / 2] {
+            let mut bad = bytes.clone();
+            bad[pos] ^= 1;
+            assert!(read_bundle(&bad).is_err(), "{pos}");
+        }
+        // truncated
+        assert!(read_bundle(&bytes[..bytes.len() / 2]).is_err());
+        // rezip with edited notes.json but the original manifest
+        let mut z = zip::ZipArchive::new(std::io::Cursor::new(bytes.clone())).unwrap();
+        let mut manifest = String::new();
+        z.by_name("manifest.json")
+            .unwrap()
+            .read_to_string(&mut manifest)
+            .unwrap();
+        let mut w = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let o = zip::write::SimpleFileOptions::default();
+        w.start_file("notes.json", o).unwrap();
+        w.write_all(
+            export_json(&data(vec![note("a", "A", "HELLO", None, 1)], vec![]))
+                .unwrap()
+                .as_bytes(),
+        )
+        .unwrap();
+        w.start_file("manifest.json", o).unwrap();
+        w.write_all(manifest.as_bytes()).unwrap();
+        let forged = w.finish().unwrap().into_inner();
+        let err = read_bundle(&forged).unwrap_err().to_string();
+        assert!(err.contains("hash") || err.contains("missing"), "{err}");
+    }
+
+    #[test]
+    fn external_urls_unique() {
+        let d = data(vec![
+        note("a","","See https://a.com/x. Also [docs](http://b.org/p?q=1) and <https://a.com/x>, ok?",None,1),
+        note("b","","no links, just http:// nothing",None,1),
+    ], vec![]);
+        let r = notes_with_urls(&d);
+        assert_eq!(
+            r,
+            vec![(
+                "a".to_string(),
+                vec![
+                    "https://a.com/x".to_string(),
+                    "http://b.org/p?q=1".to_string()
+                ]
+            )]
+        );
+    }
+
+    #[test]
+    fn auto_title() {
+        let dir = temp_dir("autotitle");
+        let long = "x".repeat(200);
+        let mut d = data(
+            vec![
+                note("a", "", "\n  First line  \nsecond", None, 1),
+                note("b", "Kept", "body", None, 1),
+                note("c", "", &long, None, 1),
+            ],
+            vec![],
+        );
+        save(dir.clone(), &mut d).unwrap();
+        assert_eq!(d.notes[0].title, "");
+        d.auto_title_from_body = true;
+        save(dir.clone(), &mut d).unwrap();
+        let l = load(dir).unwrap();
+        assert_eq!(l.notes[0].title, "First line");
+        assert_eq!(l.notes[1].title, "Kept");
+        assert_eq!(l.notes[2].title, "x".repeat(80));
+    }
+
+    #[test]
+    fn by_tag() {
+        let mut a = note("a", "", "", None, 1);
+        a.tags = vec!["x".into(), "y".into(), "x".into()];
+        let mut b = note("b", "", "", None, 1);
+        b.tags = vec!["x".into()];
+        b.archived = true;
+        let m = notes_by_tag(&data(vec![a, b, note("c", "", "", None, 1)], vec![]));
+        assert_eq!(m.len(), 2);
+        assert_eq!(m["x"], ["a"]);
+        assert_eq!(m["y"], ["a"]);
+    }
+
+    #[test]
+    fn md_ids_roundtrip() {
+        let mut a = note("a", "Alpha", "line1\n\nline2", Some("f"), 1);
+        a.color = "mint".into();
+        let mut d = data(
+            vec![a, note("b", "", "plain", None, 1)],
+            vec![folder("f", "Work", 1)],
+        );
+        let md = export_markdown(
+            &d,
+            &ExportOptions {
+                include_ids: true,
+                separator: Some("---".into()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(md.contains("### Alpha\n<!-- id: a -->\n"));
+        let mut edited = md.replace("line2", "line2 edited");
+        edited = edited.replace(
+            "*Color: paper | Pinned: false",
+            "*Color: sky | Pinned: true",
+        );
+        assert_eq!(import_markdown(&mut d, &edited, 50, new_id).unwrap(), 2);
+        assert_eq!(d.notes.len(), 2);
+        assert_eq!(d.folders.len(), 1);
+        let a = d.notes.iter().find(|n| n.id == "a").unwrap();
+        assert_eq!(
+            (
+                a.title.as_str(),
+                a.body.as_str(),
+                a.color.as_str(),
+                a.folder_id.as_deref(),
+                a.updated_at
+            ),
+            ("Alpha", "line1\n\nline2 edited", "mint", Some("f"), 50)
+        );
+        let b = d.notes.iter().find(|n| n.id == "b").unwrap();
+        assert_eq!(
+            (b.title.as_str(), b.color.as_str(), b.pinned, b.pinned_at),
+            ("", "sky", true, Some(50))
+        );
+        // without ids the import duplicates
+        let md = export_markdown(&d, &ExportOptions::default()).unwrap();
+        assert!(!md.contains("<!--"));
+        assert_eq!(import_markdown(&mut d, &md, 60, new_id).unwrap(), 2);
+        assert_eq!(d.notes.len(), 4);
+    }
+
+    #[test]
+    fn md_body_headings_roundtrip() {
+        let body = "intro\n### inner heading\n## Folder: Fake\n<!-- id: z -->\n*Color: sky | Pinned: true*\n\\already escaped\nend";
+        let mut d = data(
+            vec![note("a", "Alpha", body, Some("f"), 1)],
+            vec![folder("f", "Work", 1)],
+        );
+        let md = export_markdown(
+            &d,
+            &ExportOptions {
+                include_ids: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(md.contains("\n\\### inner heading\n"));
+        assert_eq!(import_markdown(&mut d, &md, 50, new_id).unwrap(), 1);
+        assert_eq!(d.notes.len(), 1);
+        assert_eq!(d.folders.len(), 1);
+        assert_eq!(
+            (d.notes[0].title.as_str(), d.notes[0].body.as_str()),
+            ("Alpha", body)
+        );
+        assert_eq!(
+            (d.notes[0].color.as_str(), d.notes[0].pinned),
+            ("paper", false)
+        );
+    }
+
+    #[test]
+    fn md_default_export_unescaped() {
+        let body = "# heading\n\\frac{a}{b}\n*Color: sky | Pinned: true*";
+        let d = data(vec![note("a", "Alpha", body, None, 1)], vec![]);
+        let md = export_markdown(&d, &ExportOptions::default()).unwrap();
+        assert!(md.contains(&format!("\n{}\n", body)));
+        assert!(!md.contains("\\#") && !md.contains("\\\\frac"));
+    }
+
+    #[test]
+    fn backup_now_names_file() {
+        let dir = temp_dir("bknow");
+        assert!(backup(dir.clone(), 1).unwrap().is_none());
+        save(dir.clone(), &mut data(vec![], vec![])).unwrap();
+        let p = backup(dir.clone(), 77).unwrap().unwrap();
+        assert_eq!(p.file_name().unwrap(), "notes-77.json");
+        assert!(p.exists());
+    }
+
+    #[test]
+    fn lowered_revision_limit_trims_on_save() {
+        let dir = temp_dir("revisions");
+        let mut stored = data(vec![note("a", "t", "v0", None, 0)], vec![]);
+        for i in 1..=5 {
+            let mut d = data(vec![note("a", "t", &format!("v{i}"), None, i)], vec![]);
+            carry_over_revisions(&mut d, &stored);
+            stored = d;
+        }
+        let bodies: Vec<&str> = stored.notes[0].revisions.iter().map(|r| r.body.as_str()).collect();
+        assert_eq!(bodies, ["v0", "v1", "v2", "v3", "v4"]);
+        assert_eq!(stored.max_revisions, 20);
+        save(dir.clone(), &mut stored).unwrap();
+        assert_eq!(load(dir.clone()).unwrap().notes[0].revisions.len(), 5);
+        stored.max_revisions = 2;
+        save(dir.clone(), &mut stored).unwrap();
+        let l = load(dir).unwrap();
+        let kept: Vec<(&str, u64)> = l.notes[0].revisions.iter().map(|r| (r.body.as_str(), r.updated_at)).collect();
+        assert_eq!(kept, [("v3", 3), ("v4", 4)]);
+        // Several logged edits of one note leave a single revision
+        let mut d = l.clone();
+        let edits = vec![note("a", "t", "w1", None, 6), note("a", "t", "w2", None, 7)];
+        apply_wal_edits(&mut d, edits);
+        let kept: Vec<&str> = d.notes[0].revisions.iter().map(|r| r.body.as_str()).collect();
+        assert_eq!(kept, ["v4", "v5"]);
+    }
+
+    #[test]
+    fn redundant_titles_found() {
+        let d = data(
+            vec![
+                note(
+                    "a",
+                    " Shopping ",
+                    "\n shopping list? no: SHOPPING\n",
+                    None,
+                    1,
+                ),
+                note("b", "Shopping", "\n  SHOPPING \nmilk", None, 1),
+                note("c", "", "", None, 1),
+                note("d", "Title", "Other", None, 1),
+            ],
+            vec![],
+        );
+        assert_eq!(redundant_titles(&d), ["b"]);
+    }
+
+    #[test]
+    fn recent_digest_newest_first() {
+        let d = data(
+            vec![
+                note("a", "Old", "", Some("f"), 1),
+                note("b", "Newest", "", None, 30),
+                note("c", "Mid", "", Some("f"), 20),
+            ],
+            vec![folder("f", "F", 1)],
+        );
+        let md = export_recent_markdown(&d, 2).unwrap();
+        assert!(md.starts_with("# Recently edited"));
+        assert!(md.find("Newest").unwrap() < md.find("Mid").unwrap());
+        assert!(!md.contains("Old") && !md.contains("Folder"));
+        assert_eq!(
+            export_recent_markdown(&d, 99)
+                .unwrap()
+                .matches("### ")
+                .count(),
+            3
+        );
+    }
+
+    #[test]
+    fn patch_sets_fields_atomically() {
+        let mut d = data(
+            vec![note("a", "T", "B", Some("f"), 1)],
+            vec![folder("f", "F", 1)],
+        );
+        let p: NotePatch =
+            serde_json::from_value(serde_json::json!({"color": "mint", "pinned": true})).unwrap();
+        let n = patch_note(&mut d, "a", p, 9).unwrap();
+        assert_eq!(
+            (
+                n.title.as_str(),
+                n.body.as_str(),
+                n.color.as_str(),
+                n.folder_id.as_deref(),
+                n.pinned,
+                n.pinned_at,
+                n.updated_at
+            ),
+            ("T", "B", "mint", Some("f"), true, Some(9), 9)
+        );
+        let p: NotePatch =
+            serde_json::from_value(serde_json::json!({"folderId": null, "tags": ["X", "x"]}))
+                .unwrap();
+        let n = patch_note(&mut d, "a", p, 10).unwrap();
+        assert_eq!(
+            (n.folder_id, n.tags, n.color),
+            (None, vec!["x".to_string()], "mint".to_string())
+        );
+        let bad = NotePatch {
+            title: Some("new".into()),
+            color: Some("red".into()),
+            ..Default::default()
+        };
+        assert!(patch_note(&mut d, "a", bad, 11).is_err());
+        assert_eq!(d.notes[0].title, "T");
+        assert!(patch_note(
+            &mut d,
+            "a",
+            NotePatch {
+                folder_id: Some(Some("zz".into())),
+                ..Default::default()
+            },
+            11
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn data_volume_reports_local_dir() {
+        let dir = temp_dir("vol");
+        fs::create_dir_all(&dir).unwrap();
+        let v = data_volume_info(dir.clone());
+        assert_eq!((v.kind.as_str(), v.writable), ("local", true));
+        assert!(!dir.join(".parcel-write-test").exists());
+    }
+
+    #[test]
+    fn trim_trailing_whitespace_bodies() {
+        let mut d = data(
+            vec![
+                note("a", "", "one  \ntwo\t\r\n  \n\n \n", None, 1),
+                note("b", "", "clean\nbody", None, 1),
+            ],
+            vec![],
+        );
+        assert_eq!(trim_trailing_whitespace(&mut d), 1);
+        assert_eq!(d.notes[0].body, "one\ntwo");
+        assert_eq!(trim_trailing_whitespace(&mut d), 0);
+    }
+
+    #[test]
+    fn org_export_nests_folders() {
+        let mut a = note("a", "Plan", "* not a heading\ntext", Some("f"), 1);
+        a.color = "yellow".into();
+        let d = data(
+            vec![a, note("b", "", "", None, 1)],
+            vec![folder("f", "Work", 1)],
+        );
+        let org = export_org(&d).unwrap();
+        assert!(org.contains("* Work\n** Plan\n:PROPERTIES:\n:ID: a\n:COLOR: yellow\n:PINNED: false\n:END:\n * not a heading\ntext\n"));
+        assert!(org.contains("* Notes (No Folder)\n** Untitled\n"));
+        assert!(
+            matches!(export(&d, ExportFormat::Org, &ExportOptions::default()).unwrap(), ExportOutput::Text(t) if t == org)
+        );
+    }
+
+    #[test]
+    fn titleless_notes_newest_first() {
+        let d = data(
+            vec![
+                note("a", "", "body", None, 1),
+                note("b", "", "  ", None, 9),
+                note("c", " ", "newer", None, 5),
+                note("d", "T", "x", None, 1),
+            ],
+            vec![],
+        );
+        assert_eq!(titleless_notes(&d), ["c", "a"]);
+    }
+
+    #[test]
+    fn pinned_save_version_written() {
+        for layout in ["single", "split"] {
+            let dir = temp_dir(&format!("pinver-{layout}"));
+            let mut a = note("a", "A", "", None, 1);
+            a.tags = vec!["t".into()];
+            a.protected = true;
+            let mut d = data(vec![a], vec![]);
+            d.storage_layout = layout.into();
+            set_pinned_save_version(&mut d, Some(1)).unwrap();
+            save(dir.clone(), &mut d).unwrap();
+            let raw = if layout == "single" {
+                fs::read_to_string(dir.join("parcel/notes.json")).unwrap()
+            } else {
+                let f = fs::read_to_string(dir.join("parcel/folders.json")).unwrap();
+                assert!(f.contains("\"version\": 1"));
+                fs::read_to_string(dir.join("parcel/notes/a.json")).unwrap()
+            };
+            if layout == "single" {
+                assert!(raw.contains("\"version\": 1"));
+            }
+            assert!(!raw.contains("tags") && !raw.contains("protected"), "{raw}");
+            let l = load(dir).unwrap();
+            assert_eq!(l.version, CURRENT_VERSION);
+            assert!(l.notes[0].tags.is_empty());
+            assert_eq!(l.pinned_save_version, Some(1));
+        }
+        let mut d = data(vec![], vec![]);
+        assert!(set_pinned_save_version(&mut d, Some(CURRENT_VERSION + 1)).is_err());
+    }
+
+    #[test]
+    fn edit_age_buckets_counted() {
+        use chrono::{TimeZone, Utc};
+        let ms = |y, m, d, h| {
+            Utc.with_ymd_and_hms(y, m, d, h, 0, 0)
+                .unwrap()
+                .timestamp_millis() as u64
+        };
+        // now: Wednesday 2026-04-01 10:00 UTC; ISO week started Monday 2026-03-30
+        let now = ms(2026, 4, 1, 10);
+        let d = data(
+            vec![
+                note("t1", "", "", None, ms(2026, 4, 1, 0)),
+                note("w1", "", "", None, ms(2026, 3, 31, 23)), // this week, previous month
+                note("w2", "", "", None, ms(2026, 3, 30, 0)),
+                note("y1", "", "", None, ms(2026, 3, 29, 23)), // Sunday before: last month, same year
+                note("y2", "", "", None, ms(2026, 1, 1, 0)),
+                note("o1", "", "", None, ms(2025, 12, 31, 23)),
+            ],
+            vec![],
+        );
+        assert_eq!(
+            edit_age_summary(&d, now),
+            EditAgeSummary {
+                today: 1,
+                this_week: 2,
+                this_month: 0,
+                this_year: 2,
+                older: 1
+            }
+        );
+        let d = data(vec![note("m", "", "", None, ms(2026, 4, 15, 0))], vec![]);
+        assert_eq!(edit_age_summary(&d, ms(2026, 4, 30, 12)).this_month, 1);
+    }
+
+    #[test]
+    fn ics_export_lists_reminders() {
+        let mut a = note(
+            "a",
+            "Call Bo; maybe",
+            "bring: pens, paper\nand\\ice",
+            None,
+            1,
+        );
+        a.reminder_at = Some(1_709_649_000_000);
+        let mut long = note("l", &"y".repeat(100), "", None, 1);
+        long.reminder_at = Some(1_709_649_000_000);
+        let d = data(vec![a, note("b", "No reminder", "", None, 1), long], vec![]);
+        let ics = export_ics(&d).unwrap();
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 2);
+        assert!(ics.contains("UID:a@parcel\r\n"));
+        assert!(ics.contains("DTSTART:20240305T143000Z\r\n"));
+        assert!(ics.contains("SUMMARY:Call Bo\\; maybe\r\n"));
+        assert!(ics.contains("DESCRIPTION:bring: pens\\, paper\\nand\\\\ice\r\n"));
+        assert!(!ics.contains("No reminder"));
+        assert!(ics.split("\r\n").all(|line| line.len() <= 75));
+        assert!(ics.contains(&format!(
+            "SUMMARY:{}\r\n {}\r\n",
+            "y".repeat(67),
+            "y".repeat(33)
+        )));
+        let old: Note = serde_json::from_str(r#"{"id":"x","title":"","body":"","folderId":null,"pinned":false,"color":"paper","createdAt":1,"updatedAt":1}"#).unwrap();
+        assert_eq!(old.reminder_at, None);
+    }
+
+    #[test]
+    fn minified_save_keeps_order() {
+        let dir = temp_dir("minify");
+        let mut d = data(
+            vec![
+                note("z", "Z", "", None, 1),
+                note("a", "A", "", None, 2),
+                note("m", "M", "", None, 3),
+            ],
+            vec![folder("y", "Y", 1), folder("b", "B", 1)],
+        );
+        save(dir.clone(), &mut d).unwrap();
+        let pretty = fs::metadata(dir.join("parcel/notes.json")).unwrap().len();
+        d.minify_json = true;
+        save(dir.clone(), &mut d).unwrap();
+        let raw = fs::read_to_string(dir.join("parcel/notes.json")).unwrap();
+        assert!((raw.len() as u64) < pretty && !raw.contains('\n'));
+        let l = load(dir).unwrap();
+        let ids: Vec<&str> = l.notes.iter().map(|n| n.id.as_str()).collect();
+        assert_eq!(ids, ["z", "a", "m"]);
+        assert_eq!(l.folders[0].id, "y");
+        assert!(l.minify_json);
+    }
+
+    #[test]
+    fn recolor_all_skips_locked() {
+        let mut d = data(
+            vec![note("a", "A", "", None, 1), note("b", "B", "", None, 1)],
+            vec![],
+        );
+        set_color_locked(&mut d, "a", true).unwrap();
+        assert_eq!(recolor_all(&mut d, "mint", 1).unwrap(), 1);
+        assert_eq!(
+            (d.notes[0].color.as_str(), d.notes[1].color.as_str()),
+            ("paper", "mint")
+        );
+        let old: ParcelData = serde_json::from_str(r#"{"version":2,"notes":[{"id":"x","title":"","body":"","folderId":null,"pinned":false,"color":"paper","createdAt":1,"updatedAt":1}],"folders":[]}"#).unwrap();
+        assert!(!old.notes[0].color_locked);
+    }
+
+    #[test]
+    fn duplicate_folders_normalized() {
+        let d = data(
+            vec![],
+            vec![
+                folder("a", "Work", 1),
+                folder("b", "Home", 1),
+                folder("c", "work ", 1),
+            ],
+        );
+        assert_eq!(
+            duplicate_folder_names(&d),
+            vec![("work".to_string(), vec!["a".to_string(), "c".to_string()])]
+        );
+    }
+
+    #[test]
+    fn date_tree_months() {
+        // 2024-01-15 and 2024-03-10 UTC
+        let d = data(
+            vec![
+                note("a", "Same", "", Some("f"), 1705276800000),
+                note("b", "Same", "", None, 1710028800000),
+                note("c", "Same", "", None, 1705276800001),
+            ],
+            vec![folder("f", "F", 1)],
+        );
+        let root = temp_dir("date_tree");
+        assert_eq!(export_folders_json_tree(&d, &root, true).unwrap(), 3);
+        assert!(root.join("2024/01/same.json").exists());
+        assert!(root.join("2024/01/same-2.json").exists());
+        assert!(root.join("2024/03/same.json").exists());
+        let part: ParcelData =
+            serde_json::from_str(&fs::read_to_string(root.join("2024/01/same.json")).unwrap())
+                .unwrap();
+        assert_eq!(part.notes[0].folder_id, None);
+    }
+
+    #[test]
+    fn schema_fields_by_version() {
+        let names = |v| {
+            schema_fields(v)
+                .unwrap()
+                .into_iter()
+                .filter(|f| f.entity == "note")
+                .map(|f| f.name)
+                .collect::<Vec<_>>()
+        };
+        assert!(!names(1).contains(&"tags".to_string()));
+        assert!(names(CURRENT_VERSION).contains(&"tags".to_string()));
+        assert!(schema_fields(CURRENT_VERSION + 1).is_err());
+        // every serialized note field is described
+        let v = serde_json::to_value(Note::default()).unwrap();
+        assert_eq!(v.as_object().unwrap().len(), names(CURRENT_VERSION).len());
+    }
+
+    #[test]
+    fn auto_recover_from_backup() {
+        let dir = temp_dir("auto_recover");
+        let mut d = data(vec![note("a", "Good", "", None, 1)], vec![]);
+        d.auto_recover = true;
+        save(dir.clone(), &mut d).unwrap();
+        backup(dir.clone(), 100).unwrap();
+        let main = dir.join("parcel").join("notes.json");
+        fs::write(&main, "{ not json").unwrap();
+        let (loaded, diag) = load_with_diagnostics(dir.clone()).unwrap();
+        assert!(diag.recovered_from_backup);
+        assert_eq!(loaded.notes[0].title, "Good");
+        assert_eq!(
+            fs::read_to_string(dir.join("parcel").join("notes.json.corrupt")).unwrap(),
+            "{ not json"
+        );
+        assert!(
+            !load_with_diagnostics(dir.clone())
+                .unwrap()
+                .1
+                .recovered_from_backup
+        );
+
+        // off: corrupt file is an error and is left alone
+        let dir = temp_dir("auto_recover_off");
+        let mut d = data(vec![note("a", "Good", "", None, 1)], vec![]);
+        save(dir.clone(), &mut d).unwrap();
+        backup(dir.clone(), 100).unwrap();
+        fs::write(dir.join("parcel").join("notes.json"), "{ not json").unwrap();
+        assert!(load(dir.clone()).is_err());
+        assert!(!dir.join("parcel").join("notes.json.corrupt").exists());
+    }
+
+    #[test]
+    fn diff_json_reports_changes() {
+        let a = data(
+            vec![note("a", "A", "", None, 1), note("b", "B", "", None, 1)],
+            vec![folder("f", "F", 1)],
+        );
+        let mut b = a.clone();
+        b.notes[1].body = "changed".into();
+        b.notes.push(note("c", "C", "", None, 2));
+        let diff = diff_json(
+            &serde_json::to_string(&a).unwrap(),
+            &serde_json::to_string(&b).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            diff,
+            DataDiff {
+                added_notes: vec!["c".into()],
+                changed_notes: vec!["b".into()],
+                ..Default::default()
+            }
+        );
+        assert!(diff_json("{", "{}")
+            .unwrap_err()
+            .to_string()
+            .contains("first"));
+    }
+
+    #[test]
+    fn export_body_limit() {
+        let long = "é".repeat(80);
+        let d = data(
+            vec![
+                note("a", "Long", &long, None, 1),
+                note("b", "Short", "tiny", None, 1),
+            ],
+            vec![],
+        );
+        let options = ExportOptions {
+            body_limit: Some(50),
+            ..Default::default()
+        };
+        for format in [
+            ExportFormat::Markdown,
+            ExportFormat::Html,
+            ExportFormat::Plaintext,
+        ] {
+            let ExportOutput::Text(out) = export(&d, format, &options).unwrap() else {
+                panic!()
+            };
+            assert!(
+                out.contains(&format!("{}\u{2026}(truncated)", "é".repeat(50))),
+                "{out}"
+            );
+            assert!(!out.contains(&"é".repeat(51)));
+            assert!(out.contains("tiny") && !out.contains("tiny\u{2026}"));
+        }
+    }
+
+    #[test]
+    fn notes_predating_folder_found() {
+        let d = data(
+            vec![
+                note("old", "", "", Some("f"), 5),
+                note("ok", "", "", Some("f"), 20),
+                note("loose", "", "", None, 1),
+            ],
+            vec![folder("f", "F", 10)],
+        );
+        assert_eq!(notes_predating_folder(&d), vec!["old".to_string()]);
+    }
+
+    #[test]
+    fn folder_merged_markdown_in_order() {
+        let d = data(
+            vec![
+                note("b", "Chapter 2", "Second.", Some("f"), 2),
+                note("a", "Chapter 1", "First.", Some("f"), 1),
+                note("x", "Other", "", None, 1),
+            ],
+            vec![folder("f", "Book", 1)],
+        );
+        let md = export_folder_merged_markdown(&d, "f").unwrap();
+        assert_eq!(
+            md,
+            "# Book\n\n## Chapter 1\n\nFirst.\n\n## Chapter 2\n\nSecond.\n\n"
+        );
+        assert!(!md.contains("Color:"));
+        assert!(export_folder_merged_markdown(&d, "nope").is_err());
+    }
+
+    #[test]
+    fn passphrase_hint_round_trip() {
+        let dir = temp_dir("hint");
+        assert_eq!(read_passphrase_hint(dir.clone()).unwrap(), None);
+        write_passphrase_hint(dir.clone(), "  first pet, then the year  ").unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("parcel").join("hint.txt")).unwrap(),
+            "first pet, then the year"
+        );
+        assert_eq!(
+            read_passphrase_hint(dir.clone()).unwrap().as_deref(),
+            Some("first pet, then the year")
+        );
+        write_passphrase_hint(dir.clone(), "").unwrap();
+        assert!(!dir.join("parcel").join("hint.txt").exists());
+        assert_eq!(read_passphrase_hint(dir).unwrap(), None);
+    }
+
+    #[test]
+    fn import_merges_tags() {
+        let mut local = note("n", "Local", "", None, 1);
+        local.tags = vec!["a".into()];
+        let mut incoming = note("n", "Incoming", "", None, 2);
+        incoming.tags = vec!["B".into(), "a".into()];
+        let inc = data(vec![incoming], vec![]);
+
+        let mut d = data(vec![local.clone()], vec![]);
+        assert_eq!(
+            import_json(
+                &mut d,
+                &inc,
+                &ImportOptions {
+                    merge_tags: true,
+                    ..Default::default()
+                }
+            )
+            .unwrap()
+            .imported,
+            1
+        );
+        assert_eq!(
+            (d.notes[0].title.as_str(), d.notes[0].tags.clone()),
+            ("Incoming", vec!["a".to_string(), "b".to_string()])
+        );
+
+        let mut d = data(vec![local], vec![]);
+        import_json(&mut d, &inc, &Default::default()).unwrap();
+        assert_eq!(d.notes[0].tags, vec!["B".to_string(), "a".to_string()]);
+    }
+
+    #[test]
+    fn export_size_estimate_close() {
+        let notes_: Vec<_> = (0..20)
+            .map(|i| {
+                note(
+                    &format!("n{i}"),
+                    &format!("Note number {i}"),
+                    &"Some body text here. ".repeat(i + 1),
+                    if i % 2 == 0 { Some("f") } else { None },
+                    1,
+                )
+            })
+            .collect();
+        let d = data(notes_, vec![folder("f", "Work", 1)]);
+        for format in [
+            ExportFormat::Markdown,
+            ExportFormat::Html,
+            ExportFormat::Json,
+            ExportFormat::Csv,
+            ExportFormat::Plaintext,
+            ExportFormat::Opml,
+            ExportFormat::Org,
+            ExportFormat::Docx,
+            ExportFormat::Pdf,
+        ] {
+            let actual = match export(&d, format, &ExportOptions::default()).unwrap() {
+                ExportOutput::Text(t) => t.len(),
+                ExportOutput::Bytes(b) => b.len(),
+            };
+            let estimate = estimate_export_size(&d, format);
+            assert!(
+                estimate * 2 >= actual && estimate <= actual * 2,
+                "{format:?}: {estimate} vs {actual}"
+            );
+        }
+    }
+
+    #[test]
+    fn import_folder_ids_from_names() {
+        let options = ImportOptions {
+            folder_ids_from_names: true,
+            ..Default::default()
+        };
+        let mut d = data(vec![], vec![]);
+        let first = data(
+            vec![note("a", "A", "", Some("x1"), 1)],
+            vec![folder("x1", "Work", 1)],
+        );
+        let second = data(
+            vec![note("b", "B", "", Some("x2"), 1)],
+            vec![folder("x2", " work", 1)],
+        );
+        import_json(&mut d, &first, &options).unwrap();
+        import_json(&mut d, &second, &options).unwrap();
+        assert_eq!(d.folders.len(), 1);
+        assert_eq!(d.folders[0].id, folder_id_from_name("Work"));
+        assert!(d
+            .notes
+            .iter()
+            .all(|n| n.folder_id.as_deref() == Some(d.folders[0].id.as_str())));
+    }
+
+    #[test]
+    fn backlink_counts_sorted() {
+        let d = data(
+            vec![
+                note("lonely", "Lonely", "", None, 1),
+                note("hub", "Hub", "", None, 1),
+                note("x", "X", "see [[Hub]] and [[hub]]", None, 1),
+                note("y", "Y", "[[hub|the hub]]", None, 1),
+            ],
+            vec![],
+        );
+        let counts = notes_by_backlink_count(&d);
+        assert_eq!(counts[0], ("hub".to_string(), 2));
+        let ids: Vec<&str> = counts.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, ["hub", "lonely", "x", "y"]);
+        assert_eq!(find_backlinks(&d, "hub"), ["x", "y"]);
+    }
+
+    #[test]
+    fn note_closure_follows_links() {
+        let d = data(
+            vec![
+                note("a", "A", "to [[B]]", None, 1),
+                note("b", "B", "to [[C]] and back [[A]]", None, 1),
+                note("c", "C", "end", None, 1),
+            ],
+            vec![],
+        );
+        let two = export_note_closure_markdown(&d, "a", 2).unwrap();
+        assert!(two.contains("### A") && two.contains("### B") && two.contains("### C"));
+        assert_eq!(two.matches("### A").count(), 1);
+        let one = export_note_closure_markdown(&d, "a", 1).unwrap();
+        assert!(one.contains("### B") && !one.contains("### C"));
+        assert!(export_note_closure_markdown(&d, "zz", 1).is_err());
+    }
+
+    #[test]
+    fn duplicate_tags_fixed_in_place() {
+        let mut n = note("n", "", "", None, 1);
+        n.tags = vec!["a".into(), "b".into(), "a".into()];
+        let mut ok = note("ok", "", "", None, 1);
+        ok.tags = vec!["a".into(), "A".into()];
+        let mut d = data(vec![n, ok], vec![]);
+        assert_eq!(notes_with_duplicate_tags(&d), vec!["n".to_string()]);
+        assert_eq!(dedupe_note_tags(&mut d), 1);
+        assert_eq!(d.notes[0].tags, vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(d.notes[1].tags.len(), 2);
+    }
+
+    #[test]
+    fn trailing_newline_follows_setting() {
+        let dir = temp_dir("trailing_newline");
+        let file = dir.join("parcel").join("notes.json");
+        let mut d = data(vec![note("a", "A", "", None, 1)], vec![]);
+        d.trailing_newline = true;
+        save(dir.clone(), &mut d).unwrap();
+        let s = fs::read_to_string(&file).unwrap();
+        assert!(s.ends_with("}\n") && !s.ends_with("\n\n"));
+        d.trailing_newline = false;
+        save(dir.clone(), &mut d).unwrap();
+        assert!(fs::read_to_string(&file).unwrap().ends_with('}'));
+    }
+
+    #[test]
+    fn creation_span_finds_oldest_and_newest() {
+        let d = data(
+            vec![
+                note("a", "", "", None, 30),
+                note("b", "", "", None, 10),
+                note("c", "", "", None, 20),
+            ],
+            vec![],
+        );
+        assert_eq!(creation_span(&d), Some((10, 30)));
+        assert_eq!(creation_span(&data(vec![], vec![])), None);
+    }
+
+    #[test]
+    fn json_import_regenerates_ids() {
+        let inc = data(
+            vec![
+                note(
+                    "a",
+                    "A",
+                    "see [[b]] and [[b|B note]] and [[A]]",
+                    Some("f"),
+                    1,
+                ),
+                note("b", "B", "", Some("f"), 1),
+            ],
+            vec![folder("f", "F", 1)],
+        );
+        let mut d = data(vec![note("a", "Local A", "", None, 1)], vec![]);
+        let options = ImportOptions {
+            regenerate_ids: true,
+            ..Default::default()
+        };
+        let report = import_json(&mut d, &inc, &options).unwrap();
+        assert_eq!(report.imported, 2);
+        assert_eq!(d.notes.len(), 3);
+        let new_f = &report.id_map["f"];
+        assert_ne!(new_f, "f");
+        assert!(d.folders.iter().any(|f| &f.id == new_f));
+        let imported_a = d.notes.iter().find(|n| n.id == report.id_map["a"]).unwrap();
+        assert_eq!(imported_a.folder_id.as_ref(), Some(new_f));
+        let new_b = &report.id_map["b"];
+        assert_eq!(
+            imported_a.body,
+            format!("see [[{new_b}]] and [[{new_b}|B note]] and [[A]]")
+        );
+        assert!(d.notes.iter().any(|n| n.id == "a" && n.title == "Local A"));
+    }
+
+    #[test]
+    fn ranked_search_prefers_titles() {
+        let d = data(
+            vec![
+                note("body", "Groceries", "remember the budget", None, 1),
+                note("title", "Budget", "", None, 1),
+                note("none", "Other", "nothing", None, 1),
+                note("partial", "", "budgeting", None, 1),
+            ],
+            vec![],
+        );
+        let ranked: Vec<String> = search_ranked(&d, "budget")
+            .into_iter()
+            .map(|(n, _)| n.id)
+            .collect();
+        assert_eq!(ranked, vec!["title", "body", "partial"]);
+        assert!(search_ranked(&d, "  ").is_empty());
+    }
+
+    #[test]
+    fn yaml_export_round_trips() {
+        let mut n = note(
+            "a",
+            "Title: with colon",
+            "line one\nline \"two\"",
+            Some("f"),
+            5,
+        );
+        n.tags = vec!["x".into()];
+        n.pinned = true;
+        n.pinned_at = Some(5);
+        let mut d = data(
+            vec![n, note("b", "", "", None, 6)],
+            vec![folder("f", "F", 1)],
+        );
+        d.version = CURRENT_VERSION;
+        let yaml = export_yaml(&d).unwrap();
+        assert_eq!(import_yaml(&yaml).unwrap(), d);
+        assert!(import_yaml("version: 2\nnotes: [").is_err());
+    }
+
+    #[test]
+    fn tag_search_results_adds_tag() {
+        let mut d = data(
+            vec![
+                note("a", "Trip plan", "", None, 1),
+                note("b", "", "the TRIP budget", None, 1),
+                note("c", "Other", "", None, 1),
+            ],
+            vec![],
+        );
+        assert_eq!(tag_search_results(&mut d, "trip", " Travel ", 1).unwrap(), 2);
+        assert_eq!(d.notes[0].tags, vec!["travel".to_string()]);
+        assert_eq!(d.notes[1].tags, vec!["travel".to_string()]);
+        assert!(d.notes[2].tags.is_empty());
+        assert_eq!(tag_search_results(&mut d, "trip", "travel", 1).unwrap(), 0);
+        assert!(tag_search_results(&mut d, "  ", "travel", 1).is_err());
+    }
+
+    #[test]
+    fn read_only_load_never_writes() {
+        let dir = temp_dir("read_only_load");
+        let mut d = data(vec![note("a", "Good", "", None, 1)], vec![]);
+        d.auto_recover = true;
+        save(dir.clone(), &mut d).unwrap();
+        let (loaded, _) = load_read_only(dir.clone()).unwrap();
+        assert_eq!(loaded.notes[0].title, "Good");
+        backup(dir.clone(), 100).unwrap();
+        let main = dir.join("parcel").join("notes.json");
+        fs::write(&main, "{ not json").unwrap();
+        assert!(load_read_only(dir.clone()).is_err());
+        assert_eq!(fs::read_to_string(&main).unwrap(), "{ not json");
+        assert!(!dir.join("parcel").join("notes.json.corrupt").exists());
+        assert!(ParcelError::ReadOnly.to_string().contains("read-only"));
+    }
+
+    #[test]
+    fn unbalanced_markdown_reported() {
+        let d = data(
+            vec![
+                note("fence", "", "text\n```rust\nfn x() {}\n", None, 1),
+                note("ok", "", "**b** [[L]]\n```\n** [[\n```", None, 1),
+                note("mixed", "", "[[a] and **b", None, 1),
+            ],
+            vec![],
+        );
+        let issues = notes_with_markdown_issues(&d);
+        assert_eq!(
+            issues[0],
+            (
+                "fence".to_string(),
+                vec!["Unclosed code fence opened on line 2".to_string()]
+            )
+        );
+        assert_eq!(issues[1].0, "mixed");
+        assert_eq!(issues[1].1.len(), 2);
+        assert_eq!(issues.len(), 2);
+    }
+
+    #[test]
+    fn tree_zip_mirrors_folders() {
+        let d = data(
+            vec![
+                note("a", "Plan", "body", Some("w"), 1),
+                note("b", "Plan", "", Some("w"), 1),
+                note("c", "Loose", "", None, 1),
+                note("d", "index", "", None, 1),
+            ],
+            vec![folder("w", "Work Stuff", 1)],
+        );
+        let bytes = export_tree_zip(&d).unwrap();
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+        let mut names: Vec<String> = zip.file_names().map(String::from).collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "index-2.md",
+                "index.md",
+                "loose.md",
+                "work-stuff/plan-2.md",
+                "work-stuff/plan.md"
+            ]
+        );
+        let mut s = String::new();
+        std::io::Read::read_to_string(&mut zip.by_name("index.md").unwrap(), &mut s).unwrap();
+        assert!(s.contains("- [Plan](work-stuff/plan-2.md)"));
+    }
+
+    #[test]
+    fn identical_bodies_grouped() {
+        let d = data(
+            vec![
+                note("a", "", "same body", None, 1),
+                note("u", "", "unique", None, 1),
+                note("b", "", "same body\n", None, 1),
+                note("e1", "", "", None, 1),
+                note("e2", "", " ", None, 1),
+            ],
+            vec![],
+        );
+        assert_eq!(
+            identical_body_groups(&d),
+            vec![vec!["a".to_string(), "b".to_string()]]
+        );
+    }
+
+    #[test]
+    fn touch_on_metadata_off_keeps_updated_at() {
+        let mut d = data(vec![note("a", "A", "", None, 1)], vec![]);
+        d.touch_on_metadata = false;
+        let pin: NotePatch = serde_json::from_str(r#"{"pinned":true,"color":"mint"}"#).unwrap();
+        assert_eq!(patch_note(&mut d, "a", pin, 50).unwrap().updated_at, 1);
+        let body: NotePatch = serde_json::from_str(r#"{"body":"new"}"#).unwrap();
+        assert_eq!(patch_note(&mut d, "a", body, 60).unwrap().updated_at, 60);
+        d.folders = vec![folder("f", "F", 1), folder("g", "G", 1)];
+        set_pinned(&mut d, "a", false, 70).unwrap();
+        recolor_all(&mut d, "salmon", 71).unwrap();
+        toggle_note_folder(&mut d, "a", "f", "g", 72).unwrap();
+        tag_search_results(&mut d, "new", "x", 73).unwrap();
+        assert_eq!(
+            (d.notes[0].updated_at, d.notes[0].color.as_str()),
+            (60, "salmon")
+        );
+        d.touch_on_metadata = true;
+        set_pinned(&mut d, "a", true, 80).unwrap();
+        assert_eq!(d.notes[0].updated_at, 80);
+        recolor_all(&mut d, "mint", 81).unwrap();
+        assert_eq!(d.notes[0].updated_at, 81);
+        toggle_note_folder(&mut d, "a", "f", "g", 82).unwrap();
+        assert_eq!(d.notes[0].updated_at, 82);
+        tag_search_results(&mut d, "new", "y", 83).unwrap();
+        assert_eq!(d.notes[0].updated_at, 83);
+        let old: ParcelData =
+            serde_json::from_str(r#"{"version":2,"notes":[],"folders":[]}"#).unwrap();
+        assert!(old.touch_on_metadata);
+    }
+
+    #[test]
+    fn word_count_trend_cumulative() {
+        // 2024-01-15 and 2024-03-10 UTC
+        let d = data(
+            vec![
+                note("b", "", "three more words", None, 1710028800000),
+                note("a", "", "one two", None, 1705276800000),
+                note("c", "", "x", None, 1705276800500),
+            ],
+            vec![],
+        );
+        assert_eq!(
+            word_count_trend(&d),
+            vec![("2024-01-15".to_string(), 3), ("2024-03-10".to_string(), 6)]
+        );
+    }
+
+    #[test]
+    fn export_excludes_pinned() {
+        let mut pinned = note("p", "Pinned one", "", None, 1);
+        pinned.pinned = true;
+        let d = data(vec![pinned, note("u", "Plain one", "", None, 1)], vec![]);
+        let options = ExportOptions {
+            exclude_pinned: true,
+            ..Default::default()
+        };
+        for format in [ExportFormat::Markdown, ExportFormat::Html] {
+            let ExportOutput::Text(out) = export(&d, format, &options).unwrap() else {
+                panic!()
+            };
+            assert!(!out.contains("Pinned one") && out.contains("Plain one"));
+            let ExportOutput::Text(all) = export(&d, format, &ExportOptions::default()).unwrap()
+            else {
+                panic!()
+            };
+            assert!(all.contains("Pinned one"));
+        }
+    }
+
+    #[test]
+    fn import_rejects_future_version() {
+        let mut inc = data(vec![note("a", "A", "", None, 1)], vec![]);
+        inc.version = 99;
+        let mut d = data(vec![], vec![]);
+        let err = import_json(&mut d, &inc, &Default::default()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ParcelError>(),
+            Some(ParcelError::UnsupportedVersion {
+                found: 99,
+                supported: CURRENT_VERSION
+            })
+        ));
+        assert!(
+            err.to_string().contains("99")
+                && err.to_string().contains(&CURRENT_VERSION.to_string())
+        );
+        assert!(d.notes.is_empty());
+        assert!(import_plan(&d, &inc).is_err());
+        let mut dec = std::collections::HashMap::new();
+        dec.insert("a".to_string(), ImportDecision::TakeIncoming);
+        assert!(apply_import_plan(&mut d, &inc, &dec, &Default::default()).is_err());
+        assert!(d.notes.is_empty());
+        let yaml_err = import_yaml("version: 99\nnotes: []\nfolders: []\n").unwrap_err();
+        assert!(yaml_err.downcast_ref::<ParcelError>().is_some());
+    }
+
+    #[test]
+    fn pdf_export_paginates() {
+        let one = data(vec![note("a", "Short", "Hello", None, 1)], vec![]);
+        let bytes = export_pdf(&one).unwrap();
+        assert!(bytes.starts_with(b"%PDF-"));
+        assert_eq!(
+            printpdf::lopdf::Document::load_mem(&bytes)
+                .unwrap()
+                .get_pages()
+                .len(),
+            1
+        );
+
+        let notes: Vec<_> = (0..30)
+            .map(|i| {
+                note(
+                    &format!("n{i}"),
+                    &format!("Note {i} ✓"),
+                    &"Some body text here. ".repeat(20),
+                    if i % 2 == 0 { Some("f") } else { None },
+                    1,
+                )
+            })
+            .collect();
+        let many = data(notes, vec![folder("f", "Work", 1)]);
+        let bytes = export_pdf(&many).unwrap();
+        assert!(bytes.starts_with(b"%PDF-"));
+        let pages = printpdf::lopdf::Document::load_mem(&bytes)
+            .unwrap()
+            .get_pages()
+            .len();
+        assert!((3..=8).contains(&pages), "{pages} pages");
+    }
+}
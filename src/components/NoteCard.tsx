@@ -1,5 +1,5 @@
 import { useMemo, useEffect, useState, useRef } from "react";
-import { useNotesStore } from "../state/notes";
+import { displayTitle, useNotesStore } from "../state/notes";
 import ConfirmDialog from "./ConfirmDialog";
 
 // Helper to get platform-specific modifier key
@@ -18,7 +18,8 @@ const COLOR_CLASS: Record<string, string> = {
 };
 
 export default function NoteCard() {
-  const { selectedNote, updateNote, deleteNote, selectedNoteId, folders, undo, redo } = useNotesStore();
+  const { selectedNote, updateNote, deleteNote, selectedNoteId, folders, undo, redo, untitledPlaceholder } =
+    useNotesStore();
   const note = selectedNote();
   const [isMounted, setIsMounted] = useState(false);
   const [showDeleteConfirm, setShowDeleteConfirm] = useState(false);
@@ -176,7 +177,7 @@ export default function NoteCard() {
           }}
           className="w-full text-3xl font-semibold input-typography mb-6"
           style={{ color: "var(--text-main)" }}
-          placeholder="Untitled"
+          placeholder={untitledPlaceholder}
         />
 
         {/* Body textarea - paper feel */}
@@ -199,7 +200,7 @@ export default function NoteCard() {
       <ConfirmDialog
         isOpen={showDeleteConfirm}
         title="Delete Note"
-        message={`Are you sure you want to delete "${note.title || 'Untitled'}"? This action cannot be undone.`}
+        message={`Are you sure you want to delete "${displayTitle(note, untitledPlaceholder)}"? This action cannot be undone.`}
         confirmLabel="Delete"
         cancelLabel="Cancel"
         onConfirm={() => {
@@ -1,5 +1,6 @@
 import React, { useMemo, useState } from "react";
 import type { Note } from "../types";
+import { displayTitle, useNotesStore } from "../state/notes";
 import ContextMenu, { type MenuItem } from "./ContextMenu";
 
 type Props = {
@@ -13,7 +14,8 @@ type Props = {
 // Memoize to prevent unnecessary re-renders
 const NoteListItem = ({ note, active, onClick, onDelete, onPin }: Props) => {
   const [contextMenu, setContextMenu] = useState<{ x: number; y: number } | null>(null);
-  const title = note.title?.trim() || "Untitled";
+  const untitledPlaceholder = useNotesStore((s) => s.untitledPlaceholder);
+  const title = displayTitle(note, untitledPlaceholder);
   
   // Better preview extraction: remove markdown-like syntax, get first meaningful line
   const preview = useMemo(() => {
@@ -1,6 +1,7 @@
 import { useState, useMemo, useEffect } from "react";
 import { invoke } from "@tauri-apps/api/core";
 import { useNotesStore } from "../state/notes";
+import { DATA_VERSION } from "../types";
 import type { ParcelData } from "../types";
 
 type Theme = "light" | "dark" | "night" | "north";
@@ -55,7 +56,7 @@ export default function SettingsDialog({ isOpen, onClose, currentTheme, onThemeC
       setMessage(null);
 
       const data: ParcelData = {
-        version: 1,
+        version: DATA_VERSION,
         notes,
         folders,
       };
@@ -87,7 +88,7 @@ export default function SettingsDialog({ isOpen, onClose, currentTheme, onThemeC
       setMessage(null);
 
       const data: ParcelData = {
-        version: 1,
+        version: DATA_VERSION,
         notes,
         folders,
       };
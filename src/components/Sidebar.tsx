@@ -1,5 +1,5 @@
 import { useMemo, useState, useEffect } from "react";
-import { useNotesStore } from "../state/notes";
+import { displayTitle, useNotesStore } from "../state/notes";
 import NoteListItem from "./NoteListItem";
 import ConfirmDialog from "./ConfirmDialog";
 
@@ -25,6 +25,7 @@ export default function Sidebar() {
     createFolder,
     deleteNote,
     updateNote,
+    untitledPlaceholder,
   } = useNotesStore();
 
   const [newFolderName, setNewFolderName] = useState("");
@@ -256,7 +257,7 @@ export default function Sidebar() {
                   note={n}
                   active={n.id === selectedNoteId}
                   onClick={() => selectNote(n.id)}
-                  onDelete={() => setDeleteConfirm({ noteId: n.id, noteTitle: n.title || 'Untitled' })}
+                  onDelete={() => setDeleteConfirm({ noteId: n.id, noteTitle: displayTitle(n, untitledPlaceholder) })}
                   onPin={() => updateNote(n.id, { pinned: !n.pinned })}
                 />
               ))}
@@ -280,7 +281,7 @@ export default function Sidebar() {
                 note={n}
                 active={n.id === selectedNoteId}
                 onClick={() => selectNote(n.id)}
-                onDelete={() => setDeleteConfirm({ noteId: n.id, noteTitle: n.title || 'Untitled' })}
+                onDelete={() => setDeleteConfirm({ noteId: n.id, noteTitle: displayTitle(n, untitledPlaceholder) })}
                 onPin={() => updateNote(n.id, { pinned: !n.pinned })}
               />
             ))}
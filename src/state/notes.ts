@@ -1,5 +1,6 @@
 import { create } from "zustand";
 import { invoke } from "@tauri-apps/api/core";
+import { DATA_VERSION } from "../types";
 import type { Folder, Note, ParcelData, NoteColor } from "../types";
 import { uuid } from "../utils/id";
 
@@ -18,7 +19,9 @@ type NotesState = {
   search: string;
   activeFolderId: string | null;
   sortBy: SortOption;
+  untitledPlaceholder: string; // Shown for empty titles; backend setting
   error: string | null; // Error state for user feedback
+  touchOnMetadata: boolean; // Pin/color/folder changes bump updatedAt
   
   // Undo/Redo
   history: HistoryState[];
@@ -58,6 +61,11 @@ function now() {
   return Date.now();
 }
 
+// Same as display_title in notes.rs
+export function displayTitle(note: Note, placeholder: string): string {
+  return note.title?.trim() || placeholder;
+}
+
 function makeDefaultFolder(): Folder {
   const t = now();
   return { id: uuid(), name: "Notes", createdAt: t, updatedAt: t };
@@ -88,7 +96,9 @@ export const useNotesStore = create<NotesState>((set, get) => ({
   search: "",
   activeFolderId: null,
   sortBy: "updated",
+  untitledPlaceholder: "Untitled",
   error: null,
+  touchOnMetadata: true,
   history: [initialState],
   historyIndex: 0,
   maxHistorySize: 50,
@@ -109,7 +119,7 @@ export const useNotesStore = create<NotesState>((set, get) => ({
   },
 
   visibleNotes: () => {
-    const { notes, search, activeFolderId, sortBy } = get();
+    const { notes, search, activeFolderId, sortBy, untitledPlaceholder } = get();
     const q = search.trim().toLowerCase();
 
     const filtered = notes
@@ -134,8 +144,8 @@ export const useNotesStore = create<NotesState>((set, get) => ({
         case "created":
           return b.createdAt - a.createdAt; // Most recently created first
         case "title":
-          const titleA = (a.title || "Untitled").toLowerCase();
-          const titleB = (b.title || "Untitled").toLowerCase();
+          const titleA = displayTitle(a, untitledPlaceholder).toLowerCase();
+          const titleB = displayTitle(b, untitledPlaceholder).toLowerCase();
           return titleA.localeCompare(titleB); // Alphabetical
         default:
           return b.updatedAt - a.updatedAt;
@@ -171,9 +181,11 @@ export const useNotesStore = create<NotesState>((set, get) => ({
         folders,
         notes,
         selectedNoteId: notes[0]?.id ?? null,
+        untitledPlaceholder: data.untitledPlaceholder ?? "Untitled",
         history: [initialState],
         historyIndex: 0,
         error: null, // Clear any previous errors
+        touchOnMetadata: data.touchOnMetadata ?? true,
       });
     } catch (e) {
       const errorMessage = e instanceof Error ? e.message : String(e);
@@ -208,7 +220,7 @@ export const useNotesStore = create<NotesState>((set, get) => ({
   saveToDisk: async () => {
     try {
       const payload: ParcelData = {
-        version: 1,
+        version: DATA_VERSION,
         notes: get().notes,
         folders: get().folders,
       };
@@ -293,15 +305,31 @@ export const useNotesStore = create<NotesState>((set, get) => ({
     }
     set((s) => ({
       notes: s.notes.map((n) =>
-        n.id === id ? { ...n, ...patch, updatedAt: now() } : n
+        n.id === id
+          ? { ...n, ...patch, updatedAt: shouldTrack || s.touchOnMetadata ? now() : n.updatedAt }
+          : n
       ),
     }));
     // Push new state after update
     if (shouldTrack) {
       get().pushHistory();
     }
+    // Journal the edit right away so it survives a crash before the debounced
+    // save, which clears the journal again
+    const edited = get().notes.find((n) => n.id === id);
+    if (edited) {
+      invoke("log_note_edit", { note: edited }).catch((e) => {
+        console.error("log_note_edit failed:", e);
+      });
+    }
   },
   deleteNote: (id) => {
+    // Protected notes are only deleted by the backend's delete_note with force;
+    // saving without one would put it back anyway
+    if (get().notes.find((n) => n.id === id)?.protected) {
+      set({ error: "This note is protected. Unprotect it before deleting." });
+      return;
+    }
     const { pushHistory } = get();
     pushHistory();
     set((s) => {
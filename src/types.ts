@@ -15,6 +15,20 @@ export type Note = {
   color: NoteColor;
   createdAt: number; // epoch ms
   updatedAt: number; // epoch ms
+  tags?: string[]; // added in data version 2
+  protected?: boolean;
+  archived?: boolean;
+  pinnedAt?: number | null;
+  attachments?: string[]; // attached file names
+  revisions?: NoteRevision[]; // oldest first
+  reminderAt?: number | null; // epoch ms
+  colorLocked?: boolean;
+};
+
+export type NoteRevision = {
+  title: string;
+  body: string;
+  updatedAt: number; // epoch ms
 };
 
 export type Folder = {
@@ -22,10 +36,16 @@ export type Folder = {
   name: string;
   createdAt: number;
   updatedAt: number;
+  archived?: boolean;
 };
 
+// Keep in step with CURRENT_VERSION in src-tauri/src/notes.rs
+export const DATA_VERSION = 2;
+
 export type ParcelData = {
-  version: 1;
+  version: typeof DATA_VERSION;
   notes: Note[];
   folders: Folder[];
+  untitledPlaceholder?: string; // backend setting; missing means "Untitled"
+  touchOnMetadata?: boolean; // backend setting; missing means true
 };